@@ -0,0 +1,232 @@
+use crate::{SerializationFormat, TileSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Decoded tileset image as 8-bit RGBA pixels
+pub struct Atlas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Atlas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Atlas {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn open(path: &Path) -> Self {
+        let file = File::open(path)
+            .unwrap_or_else(|err| panic!("Can't open image {}: {err}", path.display()));
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().expect("Can't decode image");
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).expect("Can't decode image");
+        let buf = &buf[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf.to_vec(),
+            png::ColorType::Rgb => buf
+                .chunks(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+            png::ColorType::Indexed => unreachable!("palette is expanded by the decoder"),
+        };
+        Atlas {
+            width: info.width,
+            height: info.height,
+            pixels,
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        let file = File::create(path)
+            .unwrap_or_else(|err| panic!("Can't create image {}: {err}", path.display()));
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().expect("Can't encode image");
+        writer
+            .write_image_data(&self.pixels)
+            .expect("Can't encode image");
+    }
+
+    /// Pixels of a rectangle row by row, parts outside of the image are transparent
+    pub fn rect(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut res = vec![0; (width * height * 4) as usize];
+        if x >= self.width {
+            return res;
+        }
+        for row in 0..height.min(self.height.saturating_sub(y)) {
+            let len = width.min(self.width.saturating_sub(x)) as usize * 4;
+            let src = (((y + row) * self.width + x) * 4) as usize;
+            let dst = (row * width * 4) as usize;
+            res[dst..dst + len].copy_from_slice(&self.pixels[src..src + len]);
+        }
+        res
+    }
+
+    pub fn put_rect(&mut self, x: u32, y: u32, width: u32, pixels: &[u8]) {
+        let height = pixels.len() as u32 / 4 / width.max(1);
+        if x >= self.width {
+            return;
+        }
+        for row in 0..height.min(self.height.saturating_sub(y)) {
+            let len = width.min(self.width.saturating_sub(x)) as usize * 4;
+            let src = (row * width * 4) as usize;
+            let dst = (((y + row) * self.width + x) * 4) as usize;
+            self.pixels[dst..dst + len].copy_from_slice(&pixels[src..src + len]);
+        }
+    }
+}
+
+/// Top left pixel of a tile in the tileset image for the given number of columns
+pub fn tile_position<T>(tileset: &TileSet<T>, columns: u32, id: u32) -> (u32, u32)
+where
+    T: SerializationFormat,
+{
+    let columns = columns.max(1);
+    (
+        tileset.margin + id % columns * (tileset.tilewidth + tileset.spacing),
+        tileset.margin + id / columns * (tileset.tileheight + tileset.spacing),
+    )
+}
+
+/// Size of an image holding a grid of tiles, including margin and spacing
+pub fn image_size<T>(tileset: &TileSet<T>, columns: u32, rows: u32) -> (u32, u32)
+where
+    T: SerializationFormat,
+{
+    let size = |count: u32, tile: u32| {
+        2 * tileset.margin + count * tile + count.saturating_sub(1) * tileset.spacing
+    };
+    (
+        size(columns, tileset.tilewidth),
+        size(rows, tileset.tileheight),
+    )
+}
+
+/// Draws every tile of the image of `tileset`, laid out in `columns`, into `dst`
+/// at the cell of its new id in a grid of `dst_columns`
+pub fn copy_tiles<T>(
+    dst: &mut Atlas,
+    tileset: &TileSet<T>,
+    dir: &Path,
+    columns: u32,
+    dst_columns: u32,
+    new_id: impl Fn(u32) -> u32,
+) where
+    T: SerializationFormat,
+{
+    let image = tileset.image.as_ref().expect("Tileset has no image");
+    let src = Atlas::open(&dir.join(&image.source));
+    for id in 0..tileset.tilecount {
+        let (x, y) = tile_position(tileset, columns, id);
+        let pixels = src.rect(x, y, tileset.tilewidth, tileset.tileheight);
+        let (x, y) = tile_position(tileset, dst_columns, new_id(id));
+        dst.put_rect(x, y, tileset.tilewidth, &pixels);
+    }
+}
+
+/// Moves every tile of the image to the cell of its new id and saves the result
+/// to `output`, which becomes the new image of the tileset. Tiles without a new
+/// id are dropped. Only works on embedded tilesets, since .tsx files aren't
+/// written back.
+pub fn relayout<T>(
+    tileset: &mut TileSet<T>,
+    dir: &Path,
+    columns: u32,
+    tilecount: u32,
+    output: &Path,
+    new_id: impl Fn(u32) -> Option<u32>,
+) where
+    T: SerializationFormat,
+{
+    assert!(
+        tileset.source.is_none(),
+        "Tileset {} is stored in a .tsx file, embed it first",
+        tileset.name
+    );
+    let image = tileset.image.as_ref().expect("Tileset has no image");
+    let src = Atlas::open(&dir.join(&image.source));
+    let rows = tilecount.div_ceil(columns.max(1));
+    let (width, height) = image_size(tileset, columns, rows);
+    let mut dst = Atlas::new(width, height);
+    for id in 0..tileset.tilecount {
+        let Some(new) = new_id(id).filter(|&new| new < tilecount) else {
+            continue;
+        };
+        let (x, y) = tile_position(tileset, tileset.columns, id);
+        let pixels = src.rect(x, y, tileset.tilewidth, tileset.tileheight);
+        let (x, y) = tile_position(tileset, columns, new);
+        dst.put_rect(x, y, tileset.tilewidth, &pixels);
+    }
+    dst.save(&dir.join(output));
+
+    let image = tileset.image.as_mut().expect("Tileset has no image");
+    image.source = output.to_string_lossy().into_owned();
+    image.width = dst.width;
+    image.height = dst.height;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::XmlFormat;
+    use quick_xml::de::from_str;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Tileset of 4 tiles of 1x1 pixels in 2 columns, colored by id
+    fn setup(test: &str, source: &str) -> (PathBuf, TileSet<XmlFormat>) {
+        let dir = std::env::temp_dir().join(format!("tmx-util-atlas-{test}"));
+        fs::create_dir_all(&dir).unwrap();
+        let mut atlas = Atlas::new(2, 2);
+        for id in 0..4 {
+            atlas.put_rect(id % 2, id / 2, 1, &[id as u8 + 1, 0, 0, 255]);
+        }
+        atlas.save(&dir.join("t.png"));
+        let tileset = from_str(&format!(
+            r#"<tileset firstgid="1" {source} name="t" tilewidth="1" tileheight="1"
+                tilecount="4" columns="2">
+                <image source="t.png" width="2" height="2"/>
+            </tileset>"#
+        ))
+        .unwrap();
+        (dir, tileset)
+    }
+
+    #[test]
+    fn moves_tiles_to_their_new_cells() {
+        let (dir, mut tileset) = setup("relayout", "");
+        relayout(&mut tileset, &dir, 3, 6, Path::new("wide.png"), |id| {
+            Some(id / 2 * 3 + id % 2)
+        });
+        let image = tileset.image.as_ref().unwrap();
+        assert_eq!(
+            (image.source.as_str(), image.width, image.height),
+            ("wide.png", 3, 2)
+        );
+        let atlas = Atlas::open(&dir.join("wide.png"));
+        let red = (0..6)
+            .map(|id| atlas.rect(id % 3, id / 3, 1, 1)[0])
+            .collect::<Vec<_>>();
+        assert_eq!(red, [1, 2, 0, 3, 4, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tileset t is stored in a .tsx file, embed it first")]
+    fn refuses_tilesets_of_tsx_files() {
+        let (dir, mut tileset) = setup("relayout-tsx", r#"source="t.tsx""#);
+        relayout(&mut tileset, &dir, 3, 6, Path::new("wide.png"), Some);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    find_layer_mut, Map, Region, SerializationFormat, FLIPPED_DIAGONALLY_FLAG,
+    FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG, GID_MASK,
+};
+use std::collections::HashMap;
+
+type WangId = [u32; 8];
+
+/// Cells sharing the edge or corner at each wang id index of a cell,
+/// as `(dx, dy, index in the neighbor's wang id)`.
+/// Indices go clockwise from the top edge: even ones are edges, odd ones corners.
+const SHARED: [&[(i64, i64, usize)]; 8] = [
+    &[(0, -1, 4)],
+    &[(1, 0, 7), (0, -1, 3), (1, -1, 5)],
+    &[(1, 0, 6)],
+    &[(1, 0, 5), (0, 1, 1), (1, 1, 7)],
+    &[(0, 1, 0)],
+    &[(-1, 0, 3), (0, 1, 7), (-1, 1, 1)],
+    &[(-1, 0, 2)],
+    &[(-1, 0, 1), (0, -1, 5), (-1, -1, 3)],
+];
+
+fn transform(wangid: WangId, gid: u32) -> WangId {
+    let mut res = wangid;
+    if gid & FLIPPED_DIAGONALLY_FLAG != 0 {
+        res = std::array::from_fn(|i| res[(14 - i) % 8]);
+    }
+    if gid & FLIPPED_HORIZONTALLY_FLAG != 0 {
+        res = std::array::from_fn(|i| res[(8 - i) % 8]);
+    }
+    if gid & FLIPPED_VERTICALLY_FLAG != 0 {
+        res = std::array::from_fn(|i| res[(12 - i) % 8]);
+    }
+    res
+}
+
+fn is_uniform(wangid: &WangId) -> bool {
+    let mut colors = wangid.iter().filter(|&&c| c != 0);
+    match colors.next() {
+        Some(first) => colors.all(|c| c == first),
+        None => false,
+    }
+}
+
+/// Picks the color for a shared edge or corner from `(color, fixed, uniform)` votes.
+/// Tiles outside of the region can't change, so their colors win. Otherwise full
+/// tiles win over transitions and later colors of the wang set are painted over
+/// earlier ones, like with the terrain brush.
+fn vote(votes: &[(u32, bool, bool)]) -> u32 {
+    let any_fixed = votes.iter().any(|&(color, fixed, _)| color != 0 && fixed);
+    let votes = votes
+        .iter()
+        .filter(|&&(color, fixed, _)| color != 0 && (fixed || !any_fixed));
+    if let Some(color) = votes
+        .clone()
+        .filter(|&&(_, _, uniform)| uniform)
+        .map(|&(color, _, _)| color)
+        .max()
+    {
+        return color;
+    }
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for &(color, _, _) in votes {
+        *counts.entry(color).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(color, count)| (count, color))
+        .map(|(color, _)| color)
+        .unwrap_or(0)
+}
+
+fn mismatches(wanted: &WangId, wangid: &WangId) -> usize {
+    wanted
+        .iter()
+        .zip(wangid)
+        .filter(|(w, c)| **w != 0 && w != c)
+        .count()
+}
+
+pub fn autotile<T>(map: &mut Map<T>, layer_name: &str, wangset_name: &str, region: Option<&Region>)
+where
+    T: SerializationFormat,
+{
+    let (firstgid, wangtiles) = map
+        .tilesets
+        .iter()
+        .find_map(|tileset| {
+            let wangsets = &tileset.wangsets.as_ref()?.wangsets;
+            let wangset = wangsets.iter().find(|w| w.name == wangset_name)?;
+            let wangtiles = wangset
+                .wangtiles
+                .iter()
+                .map(|tile| {
+                    let mut wangid = [0; 8];
+                    for (dst, src) in wangid.iter_mut().zip(&tile.wangid.0) {
+                        *dst = *src;
+                    }
+                    (tile.tileid, wangid)
+                })
+                .collect::<Vec<_>>();
+            Some((tileset.firstgid, wangtiles))
+        })
+        .expect("No wang set with this name");
+    let by_tile: HashMap<u32, WangId> = wangtiles.iter().cloned().collect();
+
+    let layer = find_layer_mut(&mut map.layers, layer_name).expect("No tile layer with this name");
+    let grid = &mut layer.data.as_mut().expect("Layer has no data").data.0;
+    let height = grid.len() as i64;
+    let width = grid.first().map_or(0, |row| row.len()) as i64;
+
+    let (rx, ry, rw, rh) = match region {
+        Some(region) => (
+            region.x as i64,
+            region.y as i64,
+            region.width as i64,
+            region.height as i64,
+        ),
+        None => (0, 0, width, height),
+    };
+    let in_region = |x: i64, y: i64| x >= rx && x < rx + rw && y >= ry && y < ry + rh;
+
+    let wangid_at = |x: i64, y: i64| -> Option<WangId> {
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return None;
+        }
+        let gid = grid[y as usize][x as usize];
+        let id = (gid & GID_MASK).checked_sub(firstgid)?;
+        by_tile.get(&id).map(|wangid| transform(*wangid, gid))
+    };
+
+    let mut updates = Vec::new();
+    for y in ry.max(0)..(ry + rh).min(height) {
+        for x in rx.max(0)..(rx + rw).min(width) {
+            let Some(current) = wangid_at(x, y) else {
+                continue;
+            };
+            let mut wanted = [0; 8];
+            for (i, shared) in SHARED.iter().enumerate() {
+                let mut votes = vec![(current[i], false, is_uniform(&current))];
+                for &(dx, dy, j) in shared.iter() {
+                    if let Some(other) = wangid_at(x + dx, y + dy) {
+                        votes.push((other[j], !in_region(x + dx, y + dy), is_uniform(&other)));
+                    }
+                }
+                wanted[i] = vote(&votes);
+            }
+            if mismatches(&wanted, &current) == 0 {
+                continue;
+            }
+            let best = wangtiles
+                .iter()
+                .min_by_key(|(_, wangid)| mismatches(&wanted, wangid))
+                .map(|(tileid, _)| *tileid);
+            if let Some(tileid) = best {
+                updates.push((x as usize, y as usize, firstgid + tileid));
+            }
+        }
+    }
+
+    for (x, y, gid) in updates {
+        grid[y][x] = gid;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::XmlFormat;
+    use quick_xml::de::from_str;
+
+    /// 3x1 map with an edge wang set of grass (1) and dirt (2): tile 0 is
+    /// grass, 1 dirt, 2 grass with a dirt right edge and 3 with a dirt left edge
+    fn map(cells: &str) -> Map<XmlFormat> {
+        from_str(&format!(
+            r##"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="3" height="1" tilewidth="16" tileheight="16" nextobjectid="1">
+                <tileset firstgid="1" name="terrain" tilewidth="16" tileheight="16"
+                    tilecount="4" columns="4">
+                    <wangsets>
+                        <wangset name="Ground" type="edge" tile="-1">
+                            <wangcolor name="Grass" color="#00ff00" tile="-1" probability="1"/>
+                            <wangcolor name="Dirt" color="#808000" tile="-1" probability="1"/>
+                            <wangtile tileid="0" wangid="1,0,1,0,1,0,1,0"/>
+                            <wangtile tileid="1" wangid="2,0,2,0,2,0,2,0"/>
+                            <wangtile tileid="2" wangid="1,0,2,0,1,0,1,0"/>
+                            <wangtile tileid="3" wangid="1,0,1,0,1,0,2,0"/>
+                        </wangset>
+                    </wangsets>
+                </tileset>
+                <layer id="1" name="Ground" width="3" height="1">
+                    <data encoding="csv">{cells}</data>
+                </layer>
+            </map>"##
+        ))
+        .unwrap()
+    }
+
+    fn cells(map: &mut Map<XmlFormat>) -> Vec<u32> {
+        let layer = find_layer_mut(&mut map.layers, "Ground").unwrap();
+        layer.data.as_ref().unwrap().data.0.concat()
+    }
+
+    #[test]
+    fn flips_wang_ids_like_tiles() {
+        let wangid = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            transform(wangid, FLIPPED_HORIZONTALLY_FLAG),
+            [1, 8, 7, 6, 5, 4, 3, 2]
+        );
+        assert_eq!(
+            transform(wangid, FLIPPED_VERTICALLY_FLAG),
+            [5, 4, 3, 2, 1, 8, 7, 6]
+        );
+        assert_eq!(
+            transform(wangid, FLIPPED_DIAGONALLY_FLAG),
+            [7, 6, 5, 4, 3, 2, 1, 8]
+        );
+    }
+
+    #[test]
+    fn votes_for_fixed_then_full_then_later_colors() {
+        assert_eq!(vote(&[(1, false, true), (2, true, false)]), 2);
+        assert_eq!(vote(&[(1, false, true), (2, false, false)]), 1);
+        assert_eq!(vote(&[(1, false, true), (2, false, true)]), 2);
+        assert_eq!(vote(&[(0, false, true), (0, true, true)]), 0);
+    }
+
+    #[test]
+    fn adds_transitions_next_to_other_colors() {
+        let mut map = map("1,2,1");
+        autotile(&mut map, "Ground", "Ground", None);
+        assert_eq!(cells(&mut map), [3, 2, 4]);
+    }
+
+    #[test]
+    fn only_changes_cells_in_the_region() {
+        let mut map = map("1,2,1");
+        let region = Region {
+            x: 2,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        autotile(&mut map, "Ground", "Ground", Some(&region));
+        assert_eq!(cells(&mut map), [1, 2, 4]);
+    }
+}
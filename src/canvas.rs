@@ -0,0 +1,190 @@
+use crate::{for_each_layer, Anchor, LayerType, Map, Object, Offset, SerializationFormat};
+
+/// Offset that keeps the anchored part of the map in place
+pub fn anchor_offset<T>(map: &Map<T>, width: u32, height: u32, anchor: Anchor) -> Offset
+where
+    T: SerializationFormat,
+{
+    use Anchor::*;
+    let dx = width as i32 - map.width as i32;
+    let dy = height as i32 - map.height as i32;
+    let x = match anchor {
+        TopLeft | Left | BottomLeft => 0,
+        Top | Center | Bottom => dx / 2,
+        TopRight | Right | BottomRight => dx,
+    };
+    let y = match anchor {
+        TopLeft | Top | TopRight => 0,
+        Left | Center | Right => dy / 2,
+        BottomLeft | Bottom | BottomRight => dy,
+    };
+    Offset { x, y }
+}
+
+/// Pixel bounds of an object as `(left, top, right, bottom)`, ignoring rotation
+fn bounds<T>(object: &Object<T>) -> (f64, f64, f64, f64)
+where
+    T: SerializationFormat,
+{
+    let (width, height) = (object.width.unwrap_or(0.0), object.height.unwrap_or(0.0));
+    if let Some(points) = object.polygon.as_ref().or(object.polyline.as_ref()) {
+        let xs = points.points.iter().map(|(x, _)| object.x + x);
+        let ys = points.points.iter().map(|(_, y)| object.y + y);
+        return (
+            xs.clone().fold(f64::INFINITY, f64::min),
+            ys.clone().fold(f64::INFINITY, f64::min),
+            xs.fold(f64::NEG_INFINITY, f64::max),
+            ys.fold(f64::NEG_INFINITY, f64::max),
+        );
+    }
+    if object.gid.is_some() {
+        // Tile objects are placed by their bottom left corner
+        return (object.x, object.y - height, object.x + width, object.y);
+    }
+    (object.x, object.y, object.x + width, object.y + height)
+}
+
+/// Whether `start..end` overlaps `0..limit`, empty ranges count when they are inside
+fn overlaps(start: f64, end: f64, limit: f64) -> bool {
+    if start == end {
+        start >= 0.0 && start < limit
+    } else {
+        end > 0.0 && start < limit
+    }
+}
+
+fn shift_image_layers<T>(layers: &mut [LayerType<T>], dx: f64, dy: f64)
+where
+    T: SerializationFormat,
+{
+    for layer_type in layers {
+        match layer_type {
+            LayerType::ImageLayer(layer) => {
+                *layer.offsetx.get_or_insert(0.0) += dx;
+                *layer.offsety.get_or_insert(0.0) += dy;
+            }
+            LayerType::Group(group) => shift_image_layers(&mut group.layers, dx, dy),
+            _ => {}
+        }
+    }
+}
+
+/// Resizes the map to `width`x`height` tiles with the old content moved by `offset`
+pub fn resize_map<T>(
+    map: &mut Map<T>,
+    width: u32,
+    height: u32,
+    offset: Offset,
+    remove_outside: bool,
+) where
+    T: SerializationFormat,
+{
+    let dx = offset.x as f64 * map.tilewidth as f64;
+    let dy = offset.y as f64 * map.tileheight as f64;
+    let (map_width, map_height) = (
+        (width * map.tilewidth) as f64,
+        (height * map.tileheight) as f64,
+    );
+    map.width = width;
+    map.height = height;
+
+    shift_image_layers(&mut map.layers, dx, dy);
+    for_each_layer(&mut map.layers, &mut |layer| {
+        if let Some(data) = &mut layer.data {
+            let old = &data.data.0;
+            let cell = |x: u32, y: u32| -> u32 {
+                let (x, y) = (x as i64 - offset.x as i64, y as i64 - offset.y as i64);
+                if x < 0 || y < 0 {
+                    return 0;
+                }
+                old.get(y as usize)
+                    .and_then(|row| row.get(x as usize))
+                    .copied()
+                    .unwrap_or(0)
+            };
+            data.data.0 = (0..height)
+                .map(|y| (0..width).map(|x| cell(x, y)).collect())
+                .collect();
+            layer.width = Some(width);
+            layer.height = Some(height);
+        }
+        for object in &mut layer.objects {
+            object.x += dx;
+            object.y += dy;
+        }
+        if remove_outside {
+            layer.objects.retain(|object| {
+                let (left, top, right, bottom) = bounds(object);
+                overlaps(left, right, map_width) && overlaps(top, bottom, map_height)
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::XmlFormat;
+    use quick_xml::de::from_str;
+
+    /// 3x2 map of 16x16 tiles with the given objects and an image layer
+    fn map(objects: &str) -> Map<XmlFormat> {
+        from_str(&format!(
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="3" height="2" tilewidth="16" tileheight="16" nextobjectid="5">
+                <layer id="1" name="Ground" width="3" height="2">
+                    <data encoding="csv">1,2,3,
+4,5,6</data>
+                </layer>
+                <objectgroup id="2" name="Things">{objects}</objectgroup>
+                <imagelayer id="3" name="Sky"/>
+            </map>"#
+        ))
+        .unwrap()
+    }
+
+    fn cells(map: &Map<XmlFormat>) -> Vec<Vec<u32>> {
+        map.layers[0].layer().unwrap().data.clone().unwrap().data.0
+    }
+
+    #[test]
+    fn anchors_keep_their_side_in_place() {
+        let map = map("");
+        let offset = |width, height, anchor| {
+            let Offset { x, y } = anchor_offset(&map, width, height, anchor);
+            (x, y)
+        };
+        assert_eq!(offset(5, 4, Anchor::TopLeft), (0, 0));
+        assert_eq!(offset(5, 4, Anchor::Center), (1, 1));
+        assert_eq!(offset(5, 4, Anchor::BottomRight), (2, 2));
+        assert_eq!(offset(1, 1, Anchor::Right), (-2, 0));
+        assert_eq!(offset(1, 1, Anchor::Bottom), (-1, -1));
+    }
+
+    #[test]
+    fn moves_cells_objects_and_image_layers_by_the_offset() {
+        let mut map = map(r#"<object id="1" x="8" y="8"/>"#);
+        resize_map(&mut map, 4, 2, Offset { x: 1, y: -1 }, false);
+        assert_eq!(cells(&map), [[0, 4, 5, 6], [0, 0, 0, 0]]);
+        let layer = map.layers[0].layer().unwrap();
+        assert_eq!((layer.width, layer.height), (Some(4), Some(2)));
+
+        let object = &map.layers[1].layer().unwrap().objects[0];
+        assert_eq!((object.x, object.y), (24.0, -8.0));
+        let sky = map.layers[2].layer().unwrap();
+        assert_eq!((sky.offsetx, sky.offsety), (Some(16.0), Some(-16.0)));
+    }
+
+    #[test]
+    fn removes_objects_outside_of_the_new_bounds() {
+        let mut map = map(r#"<object id="1" x="40" y="8" width="16" height="16"/>
+            <object id="2" x="8" y="8" width="16" height="16"/>
+            <object id="3" gid="1" x="8" y="40" width="16" height="16"/>
+            <object id="4" x="0" y="0"><polyline points="40,0 -8,0"/></object>
+            <object id="5" x="8" y="40" width="16" height="16"/>"#);
+        resize_map(&mut map, 2, 2, Offset { x: 0, y: 0 }, true);
+        let ids = map.layers[1].layer().unwrap().objects.iter().map(|o| o.id);
+        // Tile objects reach up from their y, so 3 is still partly inside
+        assert_eq!(ids.collect::<Vec<_>>(), [2, 3, 4]);
+    }
+}
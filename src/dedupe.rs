@@ -0,0 +1,176 @@
+use crate::atlas::{tile_position, Atlas};
+use crate::tileset::index_for_gid;
+use crate::unused::matches;
+use crate::{remap_gids, Map, OutputFormat, SerializationFormat};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct Duplicates {
+    tileset: String,
+    groups: Vec<Vec<u32>>,
+}
+
+/// Groups of pixel-identical tiles of a tileset, each sorted by id
+fn find_duplicates<T>(map_path: &Path, map: &Map<T>, name: &str) -> Duplicates
+where
+    T: SerializationFormat,
+{
+    let tileset = map
+        .tilesets
+        .iter()
+        .find(|tileset| matches(tileset, map_path, name))
+        .unwrap_or_else(|| panic!("No tileset named {name}"));
+    let image = tileset.image.as_ref().expect("Tileset has no image");
+    let mut dir = map_path.parent().unwrap_or(Path::new("")).to_path_buf();
+    if let Some(source) = &tileset.source {
+        dir = dir
+            .join(source)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+    }
+    let atlas = Atlas::open(&dir.join(&image.source));
+
+    let mut tiles: HashMap<Vec<u8>, Vec<u32>> = HashMap::new();
+    for id in 0..tileset.tilecount {
+        let (x, y) = tile_position(tileset, tileset.columns, id);
+        let pixels = atlas.rect(x, y, tileset.tilewidth, tileset.tileheight);
+        tiles.entry(pixels).or_default().push(id);
+    }
+    let mut groups = tiles
+        .into_values()
+        .filter(|ids| ids.len() > 1)
+        .collect::<Vec<_>>();
+    groups.sort();
+    Duplicates {
+        tileset: tileset.name.clone(),
+        groups,
+    }
+}
+
+/// Points every use of a duplicate to the first tile of its group
+fn remap<T>(map_path: &Path, map: &mut Map<T>, name: &str, groups: &[Vec<u32>])
+where
+    T: SerializationFormat,
+{
+    let Some(firstgid) = map
+        .tilesets
+        .iter()
+        .find(|tileset| matches(tileset, map_path, name))
+        .map(|tileset| tileset.firstgid)
+    else {
+        eprintln!("{} doesn't use tileset {name}", map_path.display());
+        return;
+    };
+    let first = groups
+        .iter()
+        .flat_map(|ids| ids.iter().map(|&id| (id, ids[0])))
+        .collect::<HashMap<_, _>>();
+    let firstgids = map.tilesets.iter().map(|t| t.firstgid).collect::<Vec<_>>();
+    remap_gids(map, |gid| match index_for_gid(&firstgids, gid) {
+        Some(i) if firstgids[i] == firstgid => first
+            .get(&(gid - firstgid))
+            .map_or(gid, |&id| firstgid + id),
+        _ => gid,
+    });
+}
+
+/// Reports identical tiles of a tileset and optionally remaps `maps` so that
+/// only the first tile of every group is used; returns the maps to save
+pub fn dedupe<T>(
+    maps: Vec<(PathBuf, Map<T>)>,
+    name: &str,
+    remap_maps: bool,
+    format: OutputFormat,
+) -> Vec<(PathBuf, Map<T>)>
+where
+    T: SerializationFormat,
+{
+    let (path, map) = maps.first().expect("Needs at least one map");
+    let res = find_duplicates(path, map, name);
+
+    match format {
+        OutputFormat::Table => {
+            let duplicates = res.groups.iter().map(|ids| ids.len() - 1).sum::<usize>();
+            println!(
+                "{}: {} duplicate tiles in {} groups",
+                res.tileset,
+                duplicates,
+                res.groups.len()
+            );
+            for ids in &res.groups {
+                let rest = ids[1..]
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!("{}: {rest}", ids[0]);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&res).unwrap()),
+    }
+
+    if !remap_maps {
+        return Vec::new();
+    }
+    maps.into_iter()
+        .map(|(path, mut map)| {
+            remap(&path, &mut map, name, &res.groups);
+            (path, map)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::XmlFormat;
+    use quick_xml::de::from_str;
+    use std::fs;
+
+    /// Map using tileset a (4 tiles) before tileset b (3 tiles, 1x1 pixels),
+    /// with an image for b in which tiles 0 and 2 are the same
+    fn setup(test: &str, cells: &str) -> (PathBuf, Map<XmlFormat>) {
+        let dir = std::env::temp_dir().join(format!("tmx-util-dedupe-{test}"));
+        fs::create_dir_all(&dir).unwrap();
+        let mut atlas = Atlas::new(3, 1);
+        atlas.put_rect(0, 0, 1, &[1, 2, 3, 255]);
+        atlas.put_rect(1, 0, 1, &[4, 5, 6, 255]);
+        atlas.put_rect(2, 0, 1, &[1, 2, 3, 255]);
+        atlas.save(&dir.join("b.png"));
+        let map = from_str(&format!(
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="4" height="1" tilewidth="1" tileheight="1" nextobjectid="1">
+                <tileset firstgid="1" name="a" tilewidth="1" tileheight="1"
+                    tilecount="4" columns="4"/>
+                <tileset firstgid="5" name="b" tilewidth="1" tileheight="1"
+                    tilecount="3" columns="3">
+                    <image source="b.png" width="3" height="1"/>
+                </tileset>
+                <layer id="1" name="Ground" width="4" height="1">
+                    <data encoding="csv">{cells}</data>
+                </layer>
+            </map>"#
+        ))
+        .unwrap();
+        (dir.join("map.tmx"), map)
+    }
+
+    #[test]
+    fn finds_identical_tiles() {
+        let (path, map) = setup("find", "0,0,0,0");
+        let res = find_duplicates(&path, &map, "b");
+        assert_eq!(res.groups, [[0, 2]]);
+    }
+
+    #[test]
+    fn remaps_only_tiles_of_the_tileset() {
+        // Gids of a are below the firstgid of b and stay as they are
+        let (path, mut map) = setup("remap", "1,3,6,7");
+        remap(&path, &mut map, "b", &[vec![0, 2]]);
+        let layer = map.layers[0].layer().unwrap();
+        assert_eq!(layer.data.as_ref().unwrap().data.0, [[1, 3, 6, 5]]);
+    }
+}
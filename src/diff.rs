@@ -0,0 +1,248 @@
+use crate::{
+    all_layers, Layer, LayerType, Map, Object, OutputFormat, SerializationFormat, TileSet,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+struct CellChange {
+    layer: String,
+    x: u32,
+    y: u32,
+    old: u32,
+    new: u32,
+}
+
+#[derive(Serialize, PartialEq)]
+struct TileSetSummary {
+    firstgid: u32,
+    tilecount: u32,
+    source: Option<String>,
+    image: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TileSetChange {
+    name: String,
+    old: Option<TileSetSummary>,
+    new: Option<TileSetSummary>,
+}
+
+#[derive(Serialize)]
+struct ObjectChange {
+    id: u32,
+    name: String,
+    old_layer: Option<String>,
+    new_layer: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Diff {
+    added_layers: Vec<String>,
+    removed_layers: Vec<String>,
+    cells: Vec<CellChange>,
+    tilesets: Vec<TileSetChange>,
+    objects: Vec<ObjectChange>,
+}
+
+impl Diff {
+    fn is_empty(&self) -> bool {
+        self.added_layers.is_empty()
+            && self.removed_layers.is_empty()
+            && self.cells.is_empty()
+            && self.tilesets.is_empty()
+            && self.objects.is_empty()
+    }
+}
+
+fn summary<T>(tileset: &TileSet<T>) -> TileSetSummary
+where
+    T: SerializationFormat,
+{
+    TileSetSummary {
+        firstgid: tileset.firstgid,
+        tilecount: tileset.tilecount,
+        source: tileset.source.clone(),
+        image: tileset.image.as_ref().map(|image| image.source.clone()),
+    }
+}
+
+fn diff_cells<T>(name: &str, old: &Layer<T>, new: &Layer<T>, res: &mut Vec<CellChange>)
+where
+    T: SerializationFormat,
+{
+    let empty = Vec::new();
+    let old = old.data.as_ref().map_or(&empty, |data| &data.data.0);
+    let new = new.data.as_ref().map_or(&empty, |data| &data.data.0);
+    let cell = |grid: &[Vec<u32>], x: usize, y: usize| {
+        grid.get(y).and_then(|row| row.get(x)).copied().unwrap_or(0)
+    };
+    let height = old.len().max(new.len());
+    for y in 0..height {
+        let width = [old, new]
+            .iter()
+            .filter_map(|grid| grid.get(y).map(|row| row.len()))
+            .max()
+            .unwrap_or(0);
+        for x in 0..width {
+            let (a, b) = (cell(old, x, y), cell(new, x, y));
+            if a != b {
+                res.push(CellChange {
+                    layer: name.to_string(),
+                    x: x as u32,
+                    y: y as u32,
+                    old: a,
+                    new: b,
+                });
+            }
+        }
+    }
+}
+
+fn find<'a, T>(layers: &[(String, &'a LayerType<T>)], path: &str) -> Option<&'a Layer<T>>
+where
+    T: SerializationFormat,
+{
+    layers
+        .iter()
+        .find(|(other, _)| other == path)
+        .and_then(|(_, layer_type)| layer_type.layer())
+}
+
+/// Objects by id with the path of their layer
+fn objects<T>(map: &Map<T>) -> BTreeMap<u32, (String, &Object<T>)>
+where
+    T: SerializationFormat,
+{
+    let mut res = BTreeMap::new();
+    for (path, layer_type) in all_layers(&map.layers) {
+        for object in layer_type.layer().into_iter().flat_map(|l| &l.objects) {
+            res.insert(object.id, (path.clone(), object));
+        }
+    }
+    res
+}
+
+fn print_table(diff: &Diff) {
+    for name in &diff.removed_layers {
+        println!("- layer {name}");
+    }
+    for name in &diff.added_layers {
+        println!("+ layer {name}");
+    }
+    for change in &diff.tilesets {
+        let describe = |t: &TileSetSummary| {
+            let file = t.source.as_ref().or(t.image.as_ref());
+            format!(
+                "firstgid {}, {} tiles{}",
+                t.firstgid,
+                t.tilecount,
+                file.map_or(String::new(), |file| format!(", {file}"))
+            )
+        };
+        match (&change.old, &change.new) {
+            (Some(old), Some(new)) => println!(
+                "~ tileset {}: {} -> {}",
+                change.name,
+                describe(old),
+                describe(new)
+            ),
+            (Some(old), None) => println!("- tileset {} ({})", change.name, describe(old)),
+            (None, Some(new)) => println!("+ tileset {} ({})", change.name, describe(new)),
+            (None, None) => {}
+        }
+    }
+    for change in &diff.objects {
+        let sign = match (&change.old_layer, &change.new_layer) {
+            (Some(_), Some(_)) => "~",
+            (Some(_), None) => "-",
+            _ => "+",
+        };
+        let layer = change.new_layer.as_ref().or(change.old_layer.as_ref());
+        println!(
+            "{sign} object {} {:?} in {}",
+            change.id,
+            change.name,
+            layer.map_or("?", |layer| layer)
+        );
+    }
+    for cell in &diff.cells {
+        println!(
+            "~ {} {},{}: {} -> {}",
+            cell.layer, cell.x, cell.y, cell.old, cell.new
+        );
+    }
+}
+
+/// Prints the differences between two maps and tells whether there are any
+pub fn diff<T>(old: &Map<T>, new: &Map<T>, format: OutputFormat) -> bool
+where
+    T: SerializationFormat + PartialEq,
+{
+    let old_layers = all_layers(&old.layers);
+    let new_layers = all_layers(&new.layers);
+    let mut res = Diff {
+        added_layers: Vec::new(),
+        removed_layers: Vec::new(),
+        cells: Vec::new(),
+        tilesets: Vec::new(),
+        objects: Vec::new(),
+    };
+    for (path, layer_type) in &old_layers {
+        let Some(layer) = layer_type.layer() else {
+            continue;
+        };
+        match find(&new_layers, path) {
+            Some(other) => diff_cells(path, layer, other, &mut res.cells),
+            None => res.removed_layers.push(path.clone()),
+        }
+    }
+    for (path, _) in &new_layers {
+        if find(&old_layers, path).is_none() {
+            res.added_layers.push(path.clone());
+        }
+    }
+
+    let mut names = Vec::new();
+    for tileset in old.tilesets.iter().chain(&new.tilesets) {
+        if !names.contains(&&tileset.name) {
+            names.push(&tileset.name);
+        }
+    }
+    for name in names {
+        let old = old.tilesets.iter().find(|t| &t.name == name).map(summary);
+        let new = new.tilesets.iter().find(|t| &t.name == name).map(summary);
+        if old != new {
+            res.tilesets.push(TileSetChange {
+                name: name.clone(),
+                old,
+                new,
+            });
+        }
+    }
+
+    let (old_objects, new_objects) = (objects(old), objects(new));
+    let mut ids = old_objects
+        .keys()
+        .chain(new_objects.keys())
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    for id in ids {
+        let (a, b) = (old_objects.get(id), new_objects.get(id));
+        if a != b {
+            res.objects.push(ObjectChange {
+                id: *id,
+                name: b.or(a).map_or(String::new(), |(_, o)| o.name.clone()),
+                old_layer: a.map(|(layer, _)| layer.clone()),
+                new_layer: b.map(|(layer, _)| layer.clone()),
+            });
+        }
+    }
+
+    match format {
+        OutputFormat::Table => print_table(&res),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&res).unwrap()),
+    }
+    !res.is_empty()
+}
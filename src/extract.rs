@@ -0,0 +1,57 @@
+use crate::canvas::resize_map;
+use crate::layers::find_path;
+use crate::tileset::compact;
+use crate::{LayerType, Map, Offset, Region, SerializationFormat};
+
+/// Keeps the selected layers, the groups around them and everything inside of them
+fn retain_layers<T>(layers: &mut Vec<LayerType<T>>, selected: &[Vec<usize>], prefix: &[usize])
+where
+    T: SerializationFormat,
+{
+    let mut index = 0;
+    layers.retain_mut(|layer_type| {
+        let mut path = prefix.to_vec();
+        path.push(index);
+        index += 1;
+        if layer_type.layer().is_none()
+            || selected.iter().any(|selected| path.starts_with(selected))
+        {
+            return true;
+        }
+        if !selected.iter().any(|selected| selected.starts_with(&path)) {
+            return false;
+        }
+        if let Some(layer) = layer_type.layer_mut() {
+            retain_layers(&mut layer.layers, selected, &path);
+        }
+        true
+    });
+}
+
+/// Copy of the map with only the selected layers and the part inside of `region`,
+/// keeping just the tilesets it uses
+pub fn extract<T>(map: &Map<T>, selectors: &[String], region: Option<&Region>) -> Map<T>
+where
+    T: SerializationFormat + Clone,
+{
+    let mut res = map.clone();
+    if !selectors.is_empty() {
+        let selected = selectors
+            .iter()
+            .map(|selector| {
+                find_path(&res.layers, selector, "")
+                    .unwrap_or_else(|| panic!("No layer named {selector}"))
+            })
+            .collect::<Vec<_>>();
+        retain_layers(&mut res.layers, &selected, &[]);
+    }
+    if let Some(region) = region {
+        let offset = Offset {
+            x: -(region.x as i32),
+            y: -(region.y as i32),
+        };
+        resize_map(&mut res, region.width, region.height, offset, true);
+    }
+    compact(&mut res);
+    res
+}
@@ -0,0 +1,52 @@
+use crate::{find_layer_mut, Map, SerializationFormat};
+use std::collections::VecDeque;
+
+const NEIGHBORS: [(isize, isize); 8] = [
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (1, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+];
+
+pub fn fill<T>(map: &mut Map<T>, layer_name: &str, x: u32, y: u32, replace: u32, diagonal: bool)
+where
+    T: SerializationFormat,
+{
+    let layer = find_layer_mut(&mut map.layers, layer_name).expect("No tile layer with this name");
+    let grid = &mut layer.data.as_mut().expect("Layer has no data").data.0;
+    let (x, y) = (x as usize, y as usize);
+    let find = *grid
+        .get(y)
+        .and_then(|row| row.get(x))
+        .expect("Starting cell is outside of the layer");
+    let replace = replace + 1;
+    if find == replace {
+        return;
+    }
+
+    let neighbors = if diagonal {
+        &NEIGHBORS[..]
+    } else {
+        &NEIGHBORS[..4]
+    };
+    let mut queue = VecDeque::from([(x, y)]);
+    grid[y][x] = replace;
+    while let Some((x, y)) = queue.pop_front() {
+        for (dx, dy) in neighbors {
+            let (Some(nx), Some(ny)) = (x.checked_add_signed(*dx), y.checked_add_signed(*dy))
+            else {
+                continue;
+            };
+            if let Some(cell) = grid.get_mut(ny).and_then(|row| row.get_mut(nx)) {
+                if *cell == find {
+                    *cell = replace;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+}
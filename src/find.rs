@@ -0,0 +1,112 @@
+use crate::{all_layers, tileset_for_gid, Map, OutputFormat, SerializationFormat, GID_MASK};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct CellMatch {
+    layer: String,
+    x: u32,
+    y: u32,
+    gid: u32,
+    id: u32,
+}
+
+#[derive(Serialize)]
+struct ObjectMatch {
+    layer: String,
+    object: u32,
+    name: String,
+    x: f64,
+    y: f64,
+    gid: u32,
+    id: u32,
+}
+
+#[derive(Serialize)]
+struct Matches {
+    tileset: String,
+    cells: Vec<CellMatch>,
+    objects: Vec<ObjectMatch>,
+}
+
+pub fn find<T>(map: &Map<T>, tile: Option<u32>, tileset: Option<&str>, format: OutputFormat)
+where
+    T: SerializationFormat,
+{
+    let target = match tileset {
+        Some(name) => map
+            .tilesets
+            .iter()
+            .find(|tileset| tileset.name == name)
+            .expect("No tileset with this name"),
+        None => map.tilesets.first().expect("Needs at least one tileset"),
+    };
+    let local_id = |gid: u32| -> Option<u32> {
+        let tileset = tileset_for_gid(&map.tilesets, gid)?;
+        let id = (gid & GID_MASK) - tileset.firstgid;
+        (tileset.firstgid == target.firstgid && tile.is_none_or(|tile| tile == id)).then_some(id)
+    };
+
+    let mut matches = Matches {
+        tileset: target.name.clone(),
+        cells: Vec::new(),
+        objects: Vec::new(),
+    };
+    for (name, layer_type) in all_layers(&map.layers) {
+        let Some(layer) = layer_type.layer() else {
+            continue;
+        };
+        if let Some(data) = &layer.data {
+            for (y, row) in data.data.0.iter().enumerate() {
+                for (x, &gid) in row.iter().enumerate() {
+                    if let Some(id) = local_id(gid) {
+                        matches.cells.push(CellMatch {
+                            layer: name.clone(),
+                            x: x as u32,
+                            y: y as u32,
+                            gid,
+                            id,
+                        });
+                    }
+                }
+            }
+        }
+        for object in &layer.objects {
+            let Some((gid, id)) = object.gid.and_then(|gid| Some((gid, local_id(gid)?))) else {
+                continue;
+            };
+            matches.objects.push(ObjectMatch {
+                layer: name.clone(),
+                object: object.id,
+                name: object.name.clone(),
+                x: object.x,
+                y: object.y,
+                gid,
+                id,
+            });
+        }
+    }
+
+    match format {
+        OutputFormat::Table => {
+            for cell in &matches.cells {
+                println!(
+                    "{}\t{},{}\t{}:{}",
+                    cell.layer, cell.x, cell.y, matches.tileset, cell.id
+                );
+            }
+            for object in &matches.objects {
+                println!(
+                    "{}\tobject {} {:?} at {},{}\t{}:{}",
+                    object.layer,
+                    object.object,
+                    object.name,
+                    object.x,
+                    object.y,
+                    matches.tileset,
+                    object.id
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&matches).unwrap()),
+    }
+}
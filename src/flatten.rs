@@ -0,0 +1,272 @@
+use crate::layers::{find_path, parent_mut};
+use crate::{next_ids, Data, DataField, Layer, LayerType, Map, SerializationFormat};
+
+/// Tile layer in drawing order with its path, visibility and offset in tiles,
+/// including the ones of the groups around it
+struct Placed<'a, T: SerializationFormat> {
+    path: Vec<usize>,
+    layer: &'a Layer<T>,
+    visible: bool,
+    dx: i64,
+    dy: i64,
+}
+
+/// Where the layers of a group end up: path, visibility and offset in tiles
+struct Parent {
+    path: Vec<usize>,
+    visible: bool,
+    dx: i64,
+    dy: i64,
+}
+
+fn collect<'a, T>(
+    layers: &'a [LayerType<T>],
+    tile: (f64, f64),
+    parent: &Parent,
+    res: &mut Vec<Placed<'a, T>>,
+) where
+    T: SerializationFormat,
+{
+    for (i, layer_type) in layers.iter().enumerate() {
+        let Some(layer) = layer_type.layer() else {
+            continue;
+        };
+        let mut path = parent.path.clone();
+        path.push(i);
+        let offset = |offset: Option<f64>, size: f64| {
+            let tiles = offset.unwrap_or(0.0) / size;
+            assert!(
+                tiles.fract() == 0.0,
+                "Offset of layer {} isn't a multiple of the tile size",
+                layer.name
+            );
+            tiles as i64
+        };
+        let placed = Placed {
+            path,
+            layer,
+            visible: parent.visible && layer.visible != Some(0),
+            dx: parent.dx + offset(layer.offsetx, tile.0),
+            dy: parent.dy + offset(layer.offsety, tile.1),
+        };
+        match layer_type {
+            LayerType::Layer(_) => res.push(placed),
+            LayerType::Group(group) => {
+                let parent = Parent {
+                    path: placed.path,
+                    visible: placed.visible,
+                    dx: placed.dx,
+                    dy: placed.dy,
+                };
+                collect(&group.layers, tile, &parent, res);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Draws the selected tile layers, or all tile layers of the selected groups,
+/// into one top-level layer that takes the place of the top-most of them, or
+/// of the top-level group containing it, so group offsets don't apply twice.
+/// Hidden layers are left out and only dropped with `drop_hidden`, since
+/// their tiles would be lost.
+pub fn merge_layers<T>(
+    map: &mut Map<T>,
+    selectors: &[String],
+    name: Option<String>,
+    drop_hidden: bool,
+) where
+    T: SerializationFormat,
+{
+    let mut selected = selectors
+        .iter()
+        .map(|selector| {
+            find_path(&map.layers, selector, "")
+                .unwrap_or_else(|| panic!("No layer named {selector}"))
+        })
+        .collect::<Vec<_>>();
+    selected.sort();
+    selected.dedup();
+    // Layers inside of selected groups go away with the group
+    let selected = selected
+        .iter()
+        .filter(|path| {
+            !selected
+                .iter()
+                .any(|other| other.len() < path.len() && path.starts_with(other))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let (last, rest) = selected.split_last().expect("Found at least one layer");
+    let (&index, parent) = last.split_last().expect("Path is never empty");
+    let name = name.unwrap_or_else(|| {
+        let layer = parent_mut(&mut map.layers, parent)[index].layer();
+        layer.expect("Found a layer").name.clone()
+    });
+
+    let (width, height) = (map.width as usize, map.height as usize);
+    let mut grid = vec![vec![0; width]; height];
+    let root = Parent {
+        path: Vec::new(),
+        visible: true,
+        dx: 0,
+        dy: 0,
+    };
+    let mut placed = Vec::new();
+    let tile = (map.tilewidth as f64, map.tileheight as f64);
+    collect(&map.layers, tile, &root, &mut placed);
+    let placed = placed
+        .iter()
+        .filter(|layer| selected.iter().any(|path| layer.path.starts_with(path)))
+        .collect::<Vec<_>>();
+    assert!(!placed.is_empty(), "No tile layers to merge");
+    if let Some(hidden) = placed.iter().find(|layer| !layer.visible) {
+        assert!(
+            drop_hidden,
+            "Layer {} is hidden, pass --drop-hidden to delete it with the merged layers",
+            hidden.layer.name
+        );
+    }
+    for layer in placed.iter().filter(|layer| layer.visible) {
+        let Some(data) = &layer.layer.data else {
+            continue;
+        };
+        for (y, row) in data.data.0.iter().enumerate() {
+            for (x, &gid) in row.iter().enumerate() {
+                let (x, y) = (x as i64 + layer.dx, y as i64 + layer.dy);
+                if gid != 0 && x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    grid[y as usize][x as usize] = gid;
+                }
+            }
+        }
+    }
+
+    let (nextlayerid, _) = next_ids(map);
+    let merged = LayerType::Layer(Layer {
+        id: nextlayerid,
+        name,
+        width: Some(map.width),
+        height: Some(map.height),
+        offsetx: None,
+        offsety: None,
+        visible: None,
+        data: Some(Data {
+            encoding: "csv".into(),
+            data: DataField(grid, Default::default()),
+        }),
+        objects: Vec::new(),
+        layers: Vec::new(),
+    });
+    map.nextlayerid = nextlayerid.map(|id| id + 1);
+
+    // Later paths first, so that removing them keeps the others valid
+    for path in selected.iter().rev() {
+        let (&index, parent) = path.split_last().expect("Path is never empty");
+        parent_mut(&mut map.layers, parent).remove(index);
+    }
+    let removed_before = rest.iter().filter(|path| path.len() == 1).count();
+    let index = if last.len() == 1 {
+        last[0] - removed_before
+    } else {
+        last[0] - removed_before + 1
+    };
+    map.layers.insert(index, merged);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{all_layers, XmlFormat};
+    use quick_xml::de::from_str;
+
+    /// 2x1 map with the given layers
+    fn map(layers: &str) -> Map<XmlFormat> {
+        from_str(&format!(
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="2" height="1" tilewidth="16" tileheight="16"
+                nextlayerid="10" nextobjectid="1">{layers}</map>"#
+        ))
+        .unwrap()
+    }
+
+    fn layer(id: u32, name: &str, cells: &str, attributes: &str) -> String {
+        format!(
+            r#"<layer id="{id}" name="{name}" width="2" height="1" {attributes}>
+                <data encoding="csv">{cells}</data>
+            </layer>"#
+        )
+    }
+
+    fn names(map: &Map<XmlFormat>) -> Vec<String> {
+        all_layers(&map.layers)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    fn cells(map: &Map<XmlFormat>, name: &str) -> Vec<u32> {
+        let layer = all_layers(&map.layers)
+            .into_iter()
+            .find(|(path, _)| path == name)
+            .and_then(|(_, layer)| layer.layer().cloned())
+            .unwrap();
+        layer.data.unwrap().data.0.concat()
+    }
+
+    #[test]
+    fn takes_the_place_of_the_top_most_layer() {
+        let mut map = map(&format!(
+            "{}{}{}{}",
+            layer(1, "Bottom", "1,1", ""),
+            layer(2, "A", "2,0", ""),
+            layer(3, "B", "0,3", ""),
+            layer(4, "Top", "4,4", "")
+        ));
+        merge_layers(&mut map, &["A".into(), "B".into()], None, false);
+        assert_eq!(names(&map), ["Bottom", "B", "Top"]);
+        assert_eq!(cells(&map, "B"), [2, 3]);
+        assert_eq!(map.nextlayerid, Some(11));
+    }
+
+    #[test]
+    fn applies_group_offsets_once() {
+        let mut map = map(&format!(
+            r#"{}<group id="2" name="G" offsetx="16">{}</group>{}"#,
+            layer(1, "Below", "2,0", ""),
+            layer(3, "Inner", "1,0", ""),
+            layer(4, "Top", "4,4", "")
+        ));
+        merge_layers(&mut map, &["Below".into(), "G/Inner".into()], None, false);
+        // Inserted above the group instead of inside of it
+        assert_eq!(names(&map), ["G", "Inner", "Top"]);
+        assert_eq!(cells(&map, "Inner"), [2, 1]);
+        let merged = map.layers[1].layer().unwrap();
+        assert_eq!((merged.offsetx, merged.offsety), (None, None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Layer Hidden is hidden, pass --drop-hidden")]
+    fn refuses_to_delete_hidden_layers() {
+        let mut map = map(&format!(
+            "{}{}",
+            layer(1, "Ground", "1,0", ""),
+            layer(2, "Hidden", "0,2", r#"visible="0""#)
+        ));
+        merge_layers(&mut map, &["Ground".into(), "Hidden".into()], None, false);
+    }
+
+    #[test]
+    fn drops_hidden_layers_when_asked() {
+        let mut map = map(&format!(
+            "{}{}{}",
+            layer(1, "Ground", "1,0", ""),
+            layer(2, "Hidden", "0,2", r#"visible="0""#),
+            layer(3, "Top", "0,3", "")
+        ));
+        let selectors = ["Ground".into(), "Hidden".into(), "Top".into()];
+        merge_layers(&mut map, &selectors, None, true);
+        assert_eq!(names(&map), ["Top"]);
+        assert_eq!(cells(&map, "Top"), [1, 3]);
+    }
+}
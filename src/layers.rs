@@ -0,0 +1,185 @@
+use crate::{
+    next_ids, Data, DataField, LayerAction, LayerKind, LayerType, Map, SerializationFormat,
+};
+
+/// Indices leading to the layer named `selector` (or with this `group/layer`
+/// path), or with this id when `selector` is a number
+pub fn find_path<T>(layers: &[LayerType<T>], selector: &str, prefix: &str) -> Option<Vec<usize>>
+where
+    T: SerializationFormat,
+{
+    let id = selector.parse::<u32>().ok();
+    for (i, layer_type) in layers.iter().enumerate() {
+        let Some(layer) = layer_type.layer() else {
+            continue;
+        };
+        let path = format!("{prefix}{}", layer.name);
+        let matches = match id {
+            Some(id) => layer.id == Some(id),
+            None => layer.name == selector || path == selector,
+        };
+        if matches {
+            return Some(vec![i]);
+        }
+        if let Some(mut res) = find_path(&layer.layers, selector, &format!("{path}/")) {
+            res.insert(0, i);
+            return Some(res);
+        }
+    }
+    None
+}
+
+pub fn parent_mut<'a, T>(
+    layers: &'a mut Vec<LayerType<T>>,
+    path: &[usize],
+) -> &'a mut Vec<LayerType<T>>
+where
+    T: SerializationFormat,
+{
+    match path.split_first() {
+        Some((&i, rest)) => {
+            let layer = layers[i]
+                .layer_mut()
+                .expect("Path only goes through layers");
+            parent_mut(&mut layer.layers, rest)
+        }
+        None => layers,
+    }
+}
+
+/// Gives the layer and everything inside of it fresh ids
+fn renumber<T>(layer_type: &mut LayerType<T>, nextlayerid: &mut Option<u32>, nextobjectid: &mut u32)
+where
+    T: SerializationFormat,
+{
+    let Some(layer) = layer_type.layer_mut() else {
+        return;
+    };
+    if let Some(id) = nextlayerid {
+        layer.id = Some(*id);
+        *id += 1;
+    }
+    for object in &mut layer.objects {
+        object.id = *nextobjectid;
+        *nextobjectid += 1;
+    }
+    for inner in &mut layer.layers {
+        renumber(inner, nextlayerid, nextobjectid);
+    }
+}
+
+/// Turns the layer into another type, refusing to drop tiles, objects or
+/// child layers on the way
+fn set_type<T>(layer_type: LayerType<T>, kind: LayerKind, width: u32, height: u32) -> LayerType<T>
+where
+    T: SerializationFormat,
+{
+    let Some(mut layer) = (match layer_type {
+        LayerType::Layer(layer)
+        | LayerType::ImageLayer(layer)
+        | LayerType::Group(layer)
+        | LayerType::ObjectGroup(layer) => Some(layer),
+        LayerType::Unknown => None,
+    }) else {
+        return LayerType::Unknown;
+    };
+    let has_tiles = layer
+        .data
+        .as_ref()
+        .is_some_and(|data| data.data.0.iter().flatten().any(|&gid| gid != 0));
+    assert!(
+        kind == LayerKind::Tile || !has_tiles,
+        "Layer {} has tiles that would be lost",
+        layer.name
+    );
+    assert!(
+        kind == LayerKind::Object || layer.objects.is_empty(),
+        "Layer {} has objects that would be lost",
+        layer.name
+    );
+    assert!(
+        kind == LayerKind::Group || layer.layers.iter().all(|x| x.layer().is_none()),
+        "Layer {} has child layers that would be lost",
+        layer.name
+    );
+
+    if kind == LayerKind::Tile {
+        if layer.data.is_none() {
+            layer.data = Some(Data {
+                encoding: "csv".into(),
+                data: DataField(
+                    vec![vec![0; width as usize]; height as usize],
+                    Default::default(),
+                ),
+            });
+        }
+        layer.width = Some(width);
+        layer.height = Some(height);
+    } else {
+        layer.data = None;
+        layer.width = None;
+        layer.height = None;
+    }
+    match kind {
+        LayerKind::Tile => LayerType::Layer(layer),
+        LayerKind::Object => LayerType::ObjectGroup(layer),
+        LayerKind::Image => LayerType::ImageLayer(layer),
+        LayerKind::Group => LayerType::Group(layer),
+    }
+}
+
+/// Index of the closest layer next to `index` in the given direction, skipping
+/// elements that aren't layers
+fn neighbor<T>(layers: &[LayerType<T>], index: usize, up: bool) -> Option<usize>
+where
+    T: SerializationFormat,
+{
+    let is_layer = |i: &usize| layers[*i].layer().is_some();
+    if up {
+        (index + 1..layers.len()).find(is_layer)
+    } else {
+        (0..index).rev().find(is_layer)
+    }
+}
+
+pub fn layer<T>(map: &mut Map<T>, selector: &str, action: LayerAction)
+where
+    T: SerializationFormat + Clone,
+{
+    let path =
+        find_path(&map.layers, selector, "").unwrap_or_else(|| panic!("No layer named {selector}"));
+    let (&index, parent_path) = path.split_last().expect("Path is never empty");
+    let (width, height) = (map.width, map.height);
+    let (mut nextlayerid, mut nextobjectid) = next_ids(map);
+    let layers = parent_mut(&mut map.layers, parent_path);
+
+    match action {
+        LayerAction::Rename { name } => {
+            layers[index].layer_mut().expect("Found a layer").name = name;
+        }
+        LayerAction::Delete => {
+            layers.remove(index);
+        }
+        LayerAction::MoveUp | LayerAction::MoveDown => {
+            let up = action == LayerAction::MoveUp;
+            match neighbor(layers, index, up) {
+                Some(other) => layers.swap(index, other),
+                None => eprintln!("Layer {selector} can't move any further"),
+            }
+        }
+        LayerAction::Duplicate => {
+            let mut copy = layers[index].clone();
+            renumber(&mut copy, &mut nextlayerid, &mut nextobjectid);
+            if let Some(layer) = copy.layer_mut() {
+                layer.name = format!("{} copy", layer.name);
+            }
+            layers.insert(index + 1, copy);
+        }
+        LayerAction::SetType { kind } => {
+            let layer_type = std::mem::replace(&mut layers[index], LayerType::Unknown);
+            layers[index] = set_type(layer_type, kind, width, height);
+        }
+    }
+    map.nextlayerid = nextlayerid;
+    map.nextobjectid = nextobjectid;
+}
@@ -0,0 +1,203 @@
+use crate::unused::matches;
+use crate::{all_layers, tileset_for_gid, Map, OutputFormat, SerializationFormat, GID_MASK};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+enum Rule {
+    /// Every layer is called like one of `allowed`
+    LayerNames { allowed: Vec<String> },
+    /// Objects of type `object_type` have all of `properties`
+    RequiredProperties {
+        #[serde(rename = "type")]
+        object_type: String,
+        properties: Vec<String>,
+    },
+    /// `layer` has no tiles of `tileset`, a tileset name or .tsx file
+    ForbiddenTileset { layer: String, tileset: String },
+    /// No layer is hidden
+    NoHiddenLayers,
+}
+
+impl Rule {
+    fn name(&self) -> &'static str {
+        match self {
+            Rule::LayerNames { .. } => "layer-names",
+            Rule::RequiredProperties { .. } => "required-properties",
+            Rule::ForbiddenTileset { .. } => "forbidden-tileset",
+            Rule::NoHiddenLayers => "no-hidden-layers",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleConfig {
+    severity: Severity,
+    #[serde(flatten)]
+    rule: Rule,
+}
+
+/// Lint rules, read from `[[rules]]` tables in TOML or a `rules` array in JSON
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    rules: Vec<RuleConfig>,
+}
+
+pub fn read_config(path: &Path) -> Config {
+    let contents = fs::read_to_string(path).expect("Should have been able to read the lint rules");
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&contents).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+    } else {
+        toml::from_str(&contents).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+    }
+}
+
+#[derive(Serialize)]
+struct Finding {
+    severity: Severity,
+    rule: &'static str,
+    location: String,
+    message: String,
+}
+
+fn check<T>(map: &Map<T>, map_path: &Path, rule: &Rule, res: &mut Vec<(String, String)>)
+where
+    T: SerializationFormat,
+{
+    let layers = all_layers(&map.layers);
+    match rule {
+        Rule::LayerNames { allowed } => {
+            for (path, layer_type) in &layers {
+                let Some(layer) = layer_type.layer() else {
+                    continue;
+                };
+                if !allowed
+                    .iter()
+                    .any(|name| *name == layer.name || name == path)
+                {
+                    res.push((format!("layer {path}"), "name isn't allowed".into()));
+                }
+            }
+        }
+        Rule::RequiredProperties {
+            object_type,
+            properties,
+        } => {
+            for (path, layer_type) in &layers {
+                let objects = layer_type.layer().into_iter().flat_map(|l| &l.objects);
+                for object in objects.filter(|object| object.kind == *object_type) {
+                    let has = |name: &String| {
+                        object
+                            .properties
+                            .iter()
+                            .flat_map(|p| &p.properties)
+                            .any(|property| property.name == *name)
+                    };
+                    for name in properties.iter().filter(|name| !has(name)) {
+                        res.push((
+                            format!("layer {path} object {}", object.id),
+                            format!("{object_type} object has no property {name}"),
+                        ));
+                    }
+                }
+            }
+        }
+        Rule::ForbiddenTileset { layer, tileset } => {
+            for (path, layer_type) in &layers {
+                let Some(data) = layer_type
+                    .layer()
+                    .filter(|l| l.name == *layer || path == layer)
+                    .and_then(|l| l.data.as_ref())
+                else {
+                    continue;
+                };
+                let cells = data.data.0.iter().enumerate().flat_map(|(y, row)| {
+                    row.iter()
+                        .enumerate()
+                        .map(move |(x, &gid)| (x, y, gid & GID_MASK))
+                });
+                let found = cells
+                    .filter(|&(_, _, gid)| {
+                        gid != 0
+                            && tileset_for_gid(&map.tilesets, gid)
+                                .is_some_and(|t| matches(t, map_path, tileset))
+                    })
+                    .map(|(x, y, _)| (x, y))
+                    .collect::<Vec<_>>();
+                if let Some((x, y)) = found.first() {
+                    res.push((
+                        format!("layer {path}"),
+                        format!(
+                            "has {} tiles of {tileset}, the first at {x},{y}",
+                            found.len()
+                        ),
+                    ));
+                }
+            }
+        }
+        Rule::NoHiddenLayers => {
+            for (path, layer_type) in &layers {
+                if layer_type.layer().is_some_and(|l| l.visible == Some(0)) {
+                    res.push((format!("layer {path}"), "is hidden".into()));
+                }
+            }
+        }
+    }
+}
+
+/// Runs the rules of `config` over the map read from `path`, returning
+/// whether a rule with severity error failed
+pub fn lint<T>(map: &Map<T>, path: &Path, config: &Config, format: OutputFormat) -> bool
+where
+    T: SerializationFormat,
+{
+    let mut res = Vec::new();
+    for RuleConfig { severity, rule } in &config.rules {
+        let mut found = Vec::new();
+        check(map, path, rule, &mut found);
+        res.extend(found.into_iter().map(|(location, message)| Finding {
+            severity: *severity,
+            rule: rule.name(),
+            location,
+            message,
+        }));
+    }
+
+    match format {
+        OutputFormat::Table => {
+            for finding in &res {
+                println!(
+                    "{}: {}: {}: {} [{}]",
+                    path.display(),
+                    finding.severity.name(),
+                    finding.location,
+                    finding.message,
+                    finding.rule
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&res).unwrap()),
+    }
+    res.iter()
+        .any(|finding| finding.severity == Severity::Error)
+}
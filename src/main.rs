@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use csv::{self, Terminator};
 use quick_xml::de::from_str;
 use quick_xml::events::BytesDecl;
@@ -10,35 +10,80 @@ use serde::ser::SerializeStruct;
 use serde::Deserializer;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map as JsonMap, Value};
-use std::error::Error;
 use std::fs;
 use std::io::Cursor;
 use std::marker::PhantomData;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+mod atlas;
+mod autotile;
+mod canvas;
+mod dedupe;
+mod diff;
+mod extract;
+mod fill;
+mod find;
+mod flatten;
+mod layers;
+mod lint;
+mod merge;
+mod split;
+mod stats;
+mod stitch;
+mod textconv;
+mod tileset;
+mod tilesize;
+mod transform;
+mod unused;
+mod validate;
+
+const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x80000000;
+const FLIPPED_VERTICALLY_FLAG: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY_FLAG: u32 = 0x20000000;
+const ROTATED_HEXAGONAL_120_FLAG: u32 = 0x10000000;
+const GID_MASK: u32 = !(FLIPPED_HORIZONTALLY_FLAG
+    | FLIPPED_VERTICALLY_FLAG
+    | FLIPPED_DIAGONALLY_FLAG
+    | ROTATED_HEXAGONAL_120_FLAG);
 
 trait SerializationFormat {
-    fn serialize_data<'a, S, T>(data: &Data<T>, serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize_data<S, T>(data: &Data<T>, serializer: S) -> Result<S::Ok, S::Error>
     where
         T: SerializationFormat,
         S: serde::Serializer;
-    fn transform_image<'a, T>(image: &Image<T>) -> JsonMap<String, Value>
+    fn transform_image<T>(image: &Image<T>) -> JsonMap<String, Value>
     where
         T: SerializationFormat;
-    fn transform_layers<'a, T>(
-        layers: &Vec<LayerType<T>>,
-        serialize_struct: &mut impl SerializeStruct,
-    ) where
+    fn transform_layers<T>(layers: &[LayerType<T>], serialize_struct: &mut impl SerializeStruct)
+    where
         T: SerializationFormat;
-    fn layer_type<'a, T>(layer: &LayerType<T>) -> Option<&str>
+    fn layer_type<T>(layer: &LayerType<T>) -> Option<&str>
     where
         T: SerializationFormat;
+    fn serialize_list<S, V>(item_name: &str, list: &[V], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+        V: Serialize;
+    fn serialize_wangid<S>(wangid: &[u32], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer;
+    fn serialize_points<S>(points: &[(f64, f64)], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer;
+    fn serialize_marker<S>(serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer;
+    fn transform_flag(flag: bool) -> Value;
     fn transform_name(name: &str) -> &str;
     fn transform_vec_name(name: &str) -> &str;
+    fn choose_name<'a>(xml_name: &'a str, json_name: &'a str) -> &'a str;
 }
 
+#[derive(Clone, Debug, PartialEq)]
 struct XmlFormat;
 impl SerializationFormat for XmlFormat {
-    fn serialize_data<'a, S, T>(data: &Data<T>, serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize_data<S, T>(data: &Data<T>, serializer: S) -> Result<S::Ok, S::Error>
     where
         T: SerializationFormat,
         S: serde::Serializer,
@@ -49,7 +94,7 @@ impl SerializationFormat for XmlFormat {
             let mut v = Vec::new();
             let mut w = csv::WriterBuilder::new()
                 .has_headers(false)
-                .terminator(Terminator::Any(',' as u8))
+                .terminator(Terminator::Any(b','))
                 .from_writer(&mut v);
             w.serialize(record).map_err(serde::ser::Error::custom)?;
             drop(w);
@@ -69,7 +114,7 @@ impl SerializationFormat for XmlFormat {
         res.end()
     }
 
-    fn transform_image<'a, T>(image: &Image<T>) -> JsonMap<String, Value>
+    fn transform_image<T>(image: &Image<T>) -> JsonMap<String, Value>
     where
         T: SerializationFormat,
     {
@@ -91,10 +136,8 @@ impl SerializationFormat for XmlFormat {
         res
     }
 
-    fn transform_layers<'a, T>(
-        layers: &Vec<LayerType<T>>,
-        serialize_struct: &mut impl SerializeStruct,
-    ) where
+    fn transform_layers<T>(layers: &[LayerType<T>], serialize_struct: &mut impl SerializeStruct)
+    where
         T: SerializationFormat,
     {
         use LayerType::*;
@@ -109,17 +152,65 @@ impl SerializationFormat for XmlFormat {
                     serialize_struct.serialize_field(T::transform_vec_name("imagelayers"), &x)
                 }
                 Group(_) => serialize_struct.serialize_field(T::transform_vec_name("groups"), &x),
+                Unknown => Ok(()),
             };
         });
     }
 
-    fn layer_type<'a, T>(_layer: &LayerType<T>) -> Option<&str>
+    fn layer_type<T>(_layer: &LayerType<T>) -> Option<&str>
     where
         T: SerializationFormat,
     {
         None
     }
 
+    fn serialize_list<S, V>(item_name: &str, list: &[V], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+        V: Serialize,
+    {
+        let mut res = serializer.serialize_map(Some(1))?;
+        res.serialize_entry(item_name, list)?;
+        res.end()
+    }
+
+    fn serialize_wangid<S>(wangid: &[u32], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let wangid = wangid
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        serializer.serialize_str(&wangid)
+    }
+
+    fn serialize_points<S>(points: &[(f64, f64)], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let points = points
+            .iter()
+            .map(|(x, y)| format!("{x},{y}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut res = serializer.serialize_map(Some(1))?;
+        res.serialize_entry("@points", &points)?;
+        res.end()
+    }
+
+    fn serialize_marker<S>(serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_unit()
+    }
+
+    fn transform_flag(flag: bool) -> Value {
+        Value::Number(u32::from(flag).into())
+    }
+
     fn transform_name(name: &str) -> &str {
         name
     }
@@ -129,11 +220,16 @@ impl SerializationFormat for XmlFormat {
         chars.next_back();
         chars.as_str()
     }
+
+    fn choose_name<'a>(xml_name: &'a str, _json_name: &'a str) -> &'a str {
+        xml_name
+    }
 }
 
+#[derive(Clone)]
 struct JsonFormat;
 impl SerializationFormat for JsonFormat {
-    fn serialize_data<'a, S, T>(data: &Data<T>, serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize_data<S, T>(data: &Data<T>, serializer: S) -> Result<S::Ok, S::Error>
     where
         T: SerializationFormat,
         S: serde::Serializer,
@@ -147,7 +243,7 @@ impl SerializationFormat for JsonFormat {
         ser.end()
     }
 
-    fn transform_image<'a, T>(image: &Image<T>) -> JsonMap<String, Value>
+    fn transform_image<T>(image: &Image<T>) -> JsonMap<String, Value>
     where
         T: SerializationFormat,
     {
@@ -158,26 +254,69 @@ impl SerializationFormat for JsonFormat {
         res
     }
 
-    fn transform_layers<'a, T>(
-        layers: &Vec<LayerType<T>>,
-        serialize_struct: &mut impl SerializeStruct,
-    ) where
+    fn transform_layers<T>(layers: &[LayerType<T>], serialize_struct: &mut impl SerializeStruct)
+    where
         T: SerializationFormat,
     {
+        let layers = layers
+            .iter()
+            .filter(|x| x.layer().is_some())
+            .collect::<Vec<_>>();
         let _ = serialize_struct.serialize_field(T::transform_vec_name("layers"), &layers);
     }
 
-    fn layer_type<'a, T>(layer: &LayerType<T>) -> Option<&str>
+    fn layer_type<T>(layer: &LayerType<T>) -> Option<&str>
     where
         T: SerializationFormat,
     {
         use LayerType::*;
-        Some(match layer {
-            Layer(_) => "tilelayer",
-            ImageLayer(_) => "imagelayer",
-            Group(_) => "group",
-            ObjectGroup(_) => "objectgroup",
-        })
+        match layer {
+            Layer(_) => Some("tilelayer"),
+            ImageLayer(_) => Some("imagelayer"),
+            Group(_) => Some("group"),
+            ObjectGroup(_) => Some("objectgroup"),
+            Unknown => None,
+        }
+    }
+
+    fn serialize_list<S, V>(_item_name: &str, list: &[V], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+        V: Serialize,
+    {
+        serializer.collect_seq(list)
+    }
+
+    fn serialize_wangid<S>(wangid: &[u32], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(wangid)
+    }
+
+    fn serialize_points<S>(points: &[(f64, f64)], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut ser = serializer.serialize_seq(Some(points.len()))?;
+        for (x, y) in points {
+            let mut point = JsonMap::new();
+            point.insert("x".into(), (*x).into());
+            point.insert("y".into(), (*y).into());
+            ser.serialize_element(&point)?;
+        }
+        ser.end()
+    }
+
+    fn serialize_marker<S>(serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bool(true)
+    }
+
+    fn transform_flag(flag: bool) -> Value {
+        Value::Bool(flag)
     }
 
     fn transform_name(name: &str) -> &str {
@@ -190,9 +329,13 @@ impl SerializationFormat for JsonFormat {
         }
     }
 
-    fn transform_vec_name<'a>(name: &str) -> &str {
+    fn transform_vec_name(name: &str) -> &str {
         name
     }
+
+    fn choose_name<'a>(_xml_name: &'a str, json_name: &'a str) -> &'a str {
+        json_name
+    }
 }
 
 #[derive(Debug, Subcommand, PartialEq)]
@@ -206,10 +349,405 @@ enum Commands {
         replace: u32,
     },
     /// Resize tileset and update all tiles
-    /// (old values are from tmx file)
-    Resize { columns: u32, tilecount: u32 },
+    /// (old values are from tmx file). The first tileset must be embedded
+    /// in the map, .tsx files aren't written back.
+    Resize {
+        columns: u32,
+        tilecount: u32,
+
+        /// Also move the tiles in the tileset image and save it to this file,
+        /// relative to the map
+        #[arg(long)]
+        image: Option<PathBuf>,
+    },
     /// Convert .tmx file to .json
     Convert,
+    /// Recompute wang set transitions on a tile layer from neighboring tiles
+    Autotile {
+        /// Name of the tile layer
+        layer: String,
+
+        /// Name of the wang set to use
+        wangset: String,
+
+        /// Only update tiles inside `x,y,width,height`
+        #[arg(long)]
+        region: Option<Region>,
+    },
+    /// Replace the connected area of the same tile starting at a cell
+    Fill {
+        /// Name of the tile layer
+        layer: String,
+
+        /// Column of the starting cell
+        x: u32,
+
+        /// Row of the starting cell
+        y: u32,
+
+        /// Tile to replace with
+        replace: u32,
+
+        /// Also spread to diagonal neighbors
+        #[arg(long)]
+        diagonal: bool,
+    },
+    /// Print tile usage statistics without changing the map
+    Stats {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// List layer cells and tile objects that use a tile
+    Find {
+        /// Tile to find, every tile of the tileset if omitted
+        #[arg(required_unless_present = "tileset")]
+        tile: Option<u32>,
+
+        /// Name of the tileset, the first one if omitted
+        #[arg(long)]
+        tileset: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Append the tiles of one tileset to another and remove it
+    MergeTilesets {
+        /// Name of the tileset to keep
+        into: String,
+
+        /// Name of the tileset to append and remove
+        from: String,
+
+        /// Save the merged tileset image to this file, relative to the map.
+        /// Required when both tilesets have an image.
+        #[arg(long)]
+        image: Option<PathBuf>,
+    },
+    /// List tiles of a tileset that none of the maps use
+    Unused {
+        /// Name or .tsx source of the tileset
+        tileset: String,
+
+        /// Other maps using the tileset
+        maps: Vec<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Grow or shrink the map, padding or cropping every layer
+    ResizeMap {
+        width: u32,
+        height: u32,
+
+        /// Part of the map that keeps its place
+        #[arg(long, value_enum, default_value_t = Anchor::TopLeft)]
+        anchor: Anchor,
+
+        /// Position of the old content in the new map as `x,y` tiles,
+        /// instead of the anchor
+        #[arg(long, allow_hyphen_values = true, conflicts_with = "anchor")]
+        offset: Option<Offset>,
+
+        /// Remove objects that end up outside of the map
+        #[arg(long)]
+        remove_outside: bool,
+    },
+    /// Join other maps to this one into a bigger map
+    Stitch {
+        /// Maps as `path@x,y` with their top left corner in tiles,
+        /// relative to the input map
+        #[arg(required = true, allow_hyphen_values = true)]
+        maps: Vec<Placement>,
+    },
+    /// Rename, delete, move, duplicate or change the type of a layer
+    Layer {
+        /// Name, `group/layer` path or id of the layer
+        layer: String,
+
+        #[command(subcommand)]
+        action: LayerAction,
+    },
+    /// Merge tile layers into one where the top-most tiles win
+    MergeLayers {
+        /// Names, `group/layer` paths or ids of tile layers,
+        /// groups are flattened with all tile layers inside of them
+        #[arg(required = true)]
+        layers: Vec<String>,
+
+        /// Name of the merged layer instead of the one of the top-most layer
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Delete hidden layers among the merged ones, their tiles aren't
+        /// drawn into the merged layer. Without it hidden layers are an error.
+        #[arg(long)]
+        drop_hidden: bool,
+    },
+    /// Copy layers or a part of the map into a new map
+    Extract {
+        /// Names, `group/layer` paths or ids of the layers to copy, all by default
+        #[arg(long)]
+        layer: Vec<String>,
+
+        /// Only copy this part of the map as `x,y,width,height` in tiles
+        #[arg(long)]
+        region: Option<Region>,
+    },
+    /// Cut the map into regions saved as separate maps with a .world file
+    Split {
+        /// Region width in tiles
+        width: u32,
+
+        /// Region height in tiles
+        height: u32,
+
+        /// Directory for the regions and the .world file
+        #[arg(long, default_value = ".")]
+        output: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = MapFormat::Tmx)]
+        format: MapFormat,
+    },
+    /// Paste the layers of another map at a tile position
+    Paste {
+        /// Map to paste
+        stamp: PathBuf,
+
+        #[arg(allow_hyphen_values = true)]
+        x: i32,
+
+        #[arg(allow_hyphen_values = true)]
+        y: i32,
+
+        /// Keep the tiles of the map where the stamp is empty
+        #[arg(long)]
+        skip_empty: bool,
+    },
+    /// Compare the map with another one, exits with 1 when they differ
+    Diff {
+        /// Newer version of the map
+        other: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Three-way merge with the map as common ancestor, for use as a git merge
+    /// driver. The result replaces `ours`, conflicting changes keep our side
+    /// and exit with 1. Maps with content the tool can't write back, like
+    /// layer properties or image layers, are left alone and exit with 1 too.
+    /// Register it with
+    /// `git config merge.tmx.driver "tmx-util %O merge-driver %A %B"`
+    /// and `*.tmx merge=tmx` in .gitattributes.
+    MergeDriver {
+        /// Our version of the map, overwritten with the result
+        ours: PathBuf,
+
+        /// Their version of the map
+        theirs: PathBuf,
+
+        /// File for the conflict report instead of stderr
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Print the file as normalized text with decoded tile data. Git shows diffs
+    /// of that with `git config diff.tmx.textconv "sh -c 'tmx-util \"\$0\" textconv'"`
+    /// and `*.tmx diff=tmx` in .gitattributes.
+    Textconv,
+    /// Check data sizes, gids, ids and referenced files, exits with 1 on problems
+    Validate {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Check the map against conventions from a TOML or JSON file of rules,
+    /// exits with 1 when a rule with severity error fails
+    Lint {
+        /// Rules like `[[rules]]` tables with `rule = "no-hidden-layers"` and
+        /// `severity = "error"`
+        config: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Mirror or rotate the whole map
+    Transform {
+        #[arg(value_enum)]
+        transformation: Transformation,
+    },
+    /// Switch the map to another tile size, scaling objects and layer offsets
+    SetTileSize {
+        tilewidth: u32,
+        tileheight: u32,
+
+        /// .tsx file replacing the map tileset with the same name
+        #[arg(long)]
+        tileset: Vec<PathBuf>,
+    },
+    /// Report pixel-identical tiles of a tileset image
+    Dedupe {
+        /// Name or .tsx source of the tileset
+        tileset: String,
+
+        /// Other maps using the tileset
+        maps: Vec<PathBuf>,
+
+        /// Use the first tile of each group instead of its duplicates
+        /// and save all maps, which needs `--in-place`
+        #[arg(long)]
+        remap: bool,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Move tiles inside a tileset and update everything that uses them
+    Reorder {
+        /// Name of the tileset
+        tileset: String,
+
+        /// CSV file with `old,new` tile ids per line
+        permutation: PathBuf,
+    },
+    /// Remove a tileset and move later tilesets down to close the gap
+    RemoveTileset {
+        /// Name of the tileset
+        tileset: String,
+
+        /// Global id of the tile to use instead, removes the tiles if omitted
+        #[arg(long)]
+        fallback: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|err| err.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(Region {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err("expected x,y,width,height".into()),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum LayerAction {
+    /// Give the layer another name
+    Rename { name: String },
+    /// Remove the layer with everything inside of it
+    Delete,
+    /// Draw the layer above the next one
+    MoveUp,
+    /// Draw the layer below the previous one
+    MoveDown,
+    /// Insert a copy with new ids above the layer
+    Duplicate,
+    /// Turn the layer into another type of layer
+    SetType {
+        #[arg(value_enum)]
+        kind: LayerKind,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum LayerKind {
+    Tile,
+    Object,
+    Image,
+    Group,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum MapFormat {
+    Tmx,
+    Json,
+}
+
+/// Point of the map that stays in place when it is resized
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Transformation {
+    FlipHorizontal,
+    FlipVertical,
+    /// Rotate clockwise by 90 degrees
+    Rotate90,
+    Rotate180,
+    /// Rotate clockwise by 270 degrees
+    Rotate270,
+}
+
+/// Shift in tiles, parsed from `x,y`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct Offset {
+    x: i32,
+    y: i32,
+}
+
+impl FromStr for Offset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<i32>().map_err(|err| err.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match values[..] {
+            [x, y] => Ok(Offset { x, y }),
+            _ => Err("expected x,y".into()),
+        }
+    }
+}
+
+/// Map placed at a tile offset, parsed from `path@x,y`
+#[derive(Debug, Clone, PartialEq)]
+struct Placement {
+    path: PathBuf,
+    offset: Offset,
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, offset) = s.rsplit_once('@').ok_or("expected path@x,y")?;
+        Ok(Placement {
+            path: path.into(),
+            offset: offset.parse()?,
+        })
+    }
 }
 
 #[derive(Debug, Parser)]
@@ -226,7 +764,7 @@ struct Cli {
     in_place: bool,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
 struct Image<T: SerializationFormat> {
     #[serde(rename = "@source")]
@@ -266,7 +804,7 @@ impl From<Image<XmlFormat>> for Image<JsonFormat> {
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
 struct Export<T: SerializationFormat> {
     #[serde(rename = "@target")]
@@ -302,7 +840,7 @@ impl From<Export<XmlFormat>> for Export<JsonFormat> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
 struct EditorSettings<T: SerializationFormat> {
     export: Export<T>,
@@ -319,39 +857,22 @@ impl From<EditorSettings<XmlFormat>> for EditorSettings<JsonFormat> {
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
-struct TileSet<T: SerializationFormat> {
-    #[serde(rename = "@firstgid")]
-    firstgid: u32,
+struct WangColor<T: SerializationFormat> {
     #[serde(rename = "@name")]
     name: String,
-    #[serde(rename = "@tilewidth")]
-    tilewidth: u32,
-    #[serde(rename = "@tileheight")]
-    tileheight: u32,
-    #[serde(rename = "@tilecount")]
-    tilecount: u32,
-    #[serde(rename = "@columns")]
-    columns: u32,
-    image: Image<T>,
-}
-
-impl From<TileSet<XmlFormat>> for TileSet<JsonFormat> {
-    fn from(tileset: TileSet<XmlFormat>) -> Self {
-        TileSet::<JsonFormat> {
-            firstgid: tileset.firstgid,
-            name: tileset.name,
-            tilewidth: tileset.tilewidth,
-            tileheight: tileset.tileheight,
-            tilecount: tileset.tilecount,
-            columns: tileset.columns,
-            image: tileset.image.into(),
-        }
-    }
+    #[serde(rename = "@color")]
+    color: String,
+    #[serde(rename = "@tile")]
+    tile: i32,
+    #[serde(rename = "@probability")]
+    probability: f64,
+    #[serde(skip)]
+    rest: PhantomData<T>,
 }
 
-impl<T> Serialize for TileSet<T>
+impl<T> Serialize for WangColor<T>
 where
     T: SerializationFormat,
 {
@@ -359,65 +880,66 @@ where
     where
         S: serde::Serializer,
     {
-        let mut res = serializer.serialize_map(Some(7))?;
-        res.serialize_entry(T::transform_name("@firstgid"), &self.firstgid)?;
-        res.serialize_entry(T::transform_name("@name"), &self.name)?;
-        res.serialize_entry(T::transform_name("@tilewidth"), &self.tilewidth)?;
-        res.serialize_entry(T::transform_name("@tileheight"), &self.tileheight)?;
-        res.serialize_entry(T::transform_name("@tilecount"), &self.tilecount)?;
-        res.serialize_entry(T::transform_name("@columns"), &self.columns)?;
-        let image = T::transform_image(&self.image);
-        for (k, v) in image.into_iter() {
-            res.serialize_entry(&k, &v)?;
-        }
+        let mut res = serializer.serialize_struct("wangcolor", 4)?;
+        res.serialize_field(T::transform_name("@name"), &self.name)?;
+        res.serialize_field(T::transform_name("@color"), &self.color)?;
+        res.serialize_field(T::transform_name("@tile"), &self.tile)?;
+        res.serialize_field(T::transform_name("@probability"), &self.probability)?;
         res.end()
     }
 }
 
-#[derive(Default, Debug, Deserialize, PartialEq)]
-#[serde(bound = "T: SerializationFormat")]
-struct DataField<T: SerializationFormat>(Vec<Vec<u32>>, PhantomData<T>);
-
-impl From<DataField<XmlFormat>> for DataField<JsonFormat> {
-    fn from(data: DataField<XmlFormat>) -> Self {
-        DataField::<JsonFormat>(data.0, Default::default())
+impl From<WangColor<XmlFormat>> for WangColor<JsonFormat> {
+    fn from(color: WangColor<XmlFormat>) -> Self {
+        WangColor::<JsonFormat> {
+            name: color.name,
+            color: color.color,
+            tile: color.tile,
+            probability: color.probability,
+            rest: Default::default(),
+        }
     }
 }
 
-#[derive(Default, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
-struct Data<T: SerializationFormat> {
-    #[serde(rename = "@encoding")]
-    encoding: String,
-    #[serde(rename = "$text", deserialize_with = "deserialize_csv")]
-    data: DataField<T>,
-}
+struct WangId<T: SerializationFormat>(Vec<u32>, PhantomData<T>);
 
-fn deserialize_csv<'de, D, T>(deserializer: D) -> Result<DataField<T>, D::Error>
+fn deserialize_wangid<'de, D, T>(deserializer: D) -> Result<WangId<T>, D::Error>
 where
     T: SerializationFormat,
     D: Deserializer<'de>,
 {
-    let mut res: Vec<Vec<u32>> = Vec::new();
     let s = String::deserialize(deserializer)?;
-    let s = s.split(",\n");
-    for s in s {
-        let mut r = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(s.as_bytes());
-        let vals = r.records();
-        let vals = vals.map(|v| v.unwrap());
-        let vals = vals.map(|v| {
-            v.iter()
-                .map(|v| v.parse::<u32>().unwrap())
-                .collect::<Vec<_>>()
-        });
-        res.append(&mut vals.collect::<Vec<_>>());
+    let wangid = s
+        .split(',')
+        .map(|v| v.trim().parse::<u32>().map_err(serde::de::Error::custom))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(WangId(wangid, Default::default()))
+}
+
+impl<T> Serialize for WangId<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_wangid(&self.0, serializer)
     }
-    Ok(DataField(res, Default::default()))
 }
 
-impl<T> Serialize for Data<T>
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct WangTile<T: SerializationFormat> {
+    #[serde(rename = "@tileid")]
+    tileid: u32,
+    #[serde(rename = "@wangid", deserialize_with = "deserialize_wangid")]
+    wangid: WangId<T>,
+}
+
+impl<T> Serialize for WangTile<T>
 where
     T: SerializationFormat,
 {
@@ -425,33 +947,38 @@ where
     where
         S: serde::Serializer,
     {
-        T::serialize_data(&self, serializer)
+        let mut res = serializer.serialize_struct("wangtile", 2)?;
+        res.serialize_field(T::transform_name("@tileid"), &self.tileid)?;
+        res.serialize_field(T::transform_name("@wangid"), &self.wangid)?;
+        res.end()
     }
 }
 
-impl From<Data<XmlFormat>> for Data<JsonFormat> {
-    fn from(data: Data<XmlFormat>) -> Self {
-        Data::<JsonFormat> {
-            encoding: data.encoding,
-            data: data.data.into(),
+impl From<WangTile<XmlFormat>> for WangTile<JsonFormat> {
+    fn from(tile: WangTile<XmlFormat>) -> Self {
+        WangTile::<JsonFormat> {
+            tileid: tile.tileid,
+            wangid: WangId(tile.wangid.0, Default::default()),
         }
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
-enum LayerType<T: SerializationFormat> {
-    #[serde(rename = "layer")]
-    Layer(Layer<T>),
-    #[serde(rename = "imagelayer")]
-    ImageLayer(Layer<T>),
-    #[serde(rename = "group")]
-    Group(Layer<T>),
-    #[serde(rename = "objectgroup")]
-    ObjectGroup(Layer<T>),
+struct WangSet<T: SerializationFormat> {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(rename = "@tile")]
+    tile: i32,
+    #[serde(rename = "wangcolor", default)]
+    colors: Vec<WangColor<T>>,
+    #[serde(rename = "wangtile", default)]
+    wangtiles: Vec<WangTile<T>>,
 }
 
-impl<T> Serialize for LayerType<T>
+impl<T> Serialize for WangSet<T>
 where
     T: SerializationFormat,
 {
@@ -459,23 +986,800 @@ where
     where
         S: serde::Serializer,
     {
-        let layer: &Layer<T> = match self {
-            LayerType::Layer(layer) => layer.into(),
-            LayerType::ImageLayer(layer) => layer.into(),
-            LayerType::Group(layer) => layer.into(),
-            LayerType::ObjectGroup(layer) => layer.into(),
-        };
+        let mut res = serializer.serialize_struct("wangset", 5)?;
+        res.serialize_field(T::transform_name("@name"), &self.name)?;
+        res.serialize_field(T::transform_name("@type"), &self.kind)?;
+        res.serialize_field(T::transform_name("@tile"), &self.tile)?;
+        res.serialize_field(T::choose_name("wangcolor", "colors"), &self.colors)?;
+        res.serialize_field(T::transform_vec_name("wangtiles"), &self.wangtiles)?;
+        res.end()
+    }
+}
 
-        let mut res = serializer.serialize_struct("layer", 8)?;
-        if let Some(layer_type) = T::layer_type(&self) {
-            res.serialize_field(T::transform_name("@type"), layer_type)?;
-        }
-        if let Some(id) = &layer.id {
-            res.serialize_field(T::transform_name("@id"), id)?;
-        }
-        res.serialize_field(T::transform_name("@name"), &layer.name)?;
-        if let Some(width) = &layer.width {
-            res.serialize_field(T::transform_name("@width"), width)?;
+impl From<WangSet<XmlFormat>> for WangSet<JsonFormat> {
+    fn from(wangset: WangSet<XmlFormat>) -> Self {
+        WangSet::<JsonFormat> {
+            name: wangset.name,
+            kind: wangset.kind,
+            tile: wangset.tile,
+            colors: wangset.colors.into_iter().map(|x| x.into()).collect(),
+            wangtiles: wangset.wangtiles.into_iter().map(|x| x.into()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct WangSets<T: SerializationFormat> {
+    #[serde(rename = "wangset", default)]
+    wangsets: Vec<WangSet<T>>,
+}
+
+impl<T> Serialize for WangSets<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_list("wangset", &self.wangsets, serializer)
+    }
+}
+
+impl From<WangSets<XmlFormat>> for WangSets<JsonFormat> {
+    fn from(wangsets: WangSets<XmlFormat>) -> Self {
+        WangSets::<JsonFormat> {
+            wangsets: wangsets.wangsets.into_iter().map(|x| x.into()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Frame<T: SerializationFormat> {
+    #[serde(rename = "@tileid")]
+    tileid: u32,
+    #[serde(rename = "@duration")]
+    duration: u32,
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+impl<T> Serialize for Frame<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("frame", 2)?;
+        res.serialize_field(T::transform_name("@tileid"), &self.tileid)?;
+        res.serialize_field(T::transform_name("@duration"), &self.duration)?;
+        res.end()
+    }
+}
+
+impl From<Frame<XmlFormat>> for Frame<JsonFormat> {
+    fn from(frame: Frame<XmlFormat>) -> Self {
+        Frame::<JsonFormat> {
+            tileid: frame.tileid,
+            duration: frame.duration,
+            rest: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Property<T: SerializationFormat> {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@type")]
+    kind: Option<String>,
+    #[serde(rename = "@value")]
+    value: Option<String>,
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+impl<T> Serialize for Property<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("property", 3)?;
+        res.serialize_field(T::transform_name("@name"), &self.name)?;
+        if let Some(kind) = &self.kind {
+            res.serialize_field(T::transform_name("@type"), kind)?;
+        }
+        if let Some(value) = &self.value {
+            res.serialize_field(T::transform_name("@value"), value)?;
+        }
+        res.end()
+    }
+}
+
+impl From<Property<XmlFormat>> for Property<JsonFormat> {
+    fn from(property: Property<XmlFormat>) -> Self {
+        Property::<JsonFormat> {
+            name: property.name,
+            kind: property.kind,
+            value: property.value,
+            rest: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Properties<T: SerializationFormat> {
+    #[serde(rename = "property", default)]
+    properties: Vec<Property<T>>,
+}
+
+impl<T> Serialize for Properties<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_list("property", &self.properties, serializer)
+    }
+}
+
+impl From<Properties<XmlFormat>> for Properties<JsonFormat> {
+    fn from(properties: Properties<XmlFormat>) -> Self {
+        Properties::<JsonFormat> {
+            properties: properties
+                .properties
+                .into_iter()
+                .map(|x| x.into())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Animation<T: SerializationFormat> {
+    #[serde(rename = "frame", default)]
+    frames: Vec<Frame<T>>,
+}
+
+impl<T> Serialize for Animation<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_list("frame", &self.frames, serializer)
+    }
+}
+
+impl From<Animation<XmlFormat>> for Animation<JsonFormat> {
+    fn from(animation: Animation<XmlFormat>) -> Self {
+        Animation::<JsonFormat> {
+            frames: animation.frames.into_iter().map(|x| x.into()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Tile<T: SerializationFormat> {
+    #[serde(rename = "@id")]
+    id: u32,
+    #[serde(rename = "@type", default)]
+    kind: String,
+    #[serde(rename = "@probability")]
+    probability: Option<f64>,
+    animation: Option<Animation<T>>,
+}
+
+impl<T> Serialize for Tile<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("tile", 4)?;
+        res.serialize_field(T::transform_name("@id"), &self.id)?;
+        if !self.kind.is_empty() {
+            res.serialize_field(T::transform_name("@type"), &self.kind)?;
+        }
+        if let Some(probability) = &self.probability {
+            res.serialize_field(T::transform_name("@probability"), probability)?;
+        }
+        if let Some(animation) = &self.animation {
+            res.serialize_field("animation", animation)?;
+        }
+        res.end()
+    }
+}
+
+impl From<Tile<XmlFormat>> for Tile<JsonFormat> {
+    fn from(tile: Tile<XmlFormat>) -> Self {
+        Tile::<JsonFormat> {
+            id: tile.id,
+            kind: tile.kind,
+            probability: tile.probability,
+            animation: tile.animation.map(|x| x.into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct TileOffset<T: SerializationFormat> {
+    #[serde(rename = "@x", default)]
+    x: i32,
+    #[serde(rename = "@y", default)]
+    y: i32,
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+impl<T> Serialize for TileOffset<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("tileoffset", 2)?;
+        res.serialize_field(T::transform_name("@x"), &self.x)?;
+        res.serialize_field(T::transform_name("@y"), &self.y)?;
+        res.end()
+    }
+}
+
+impl From<TileOffset<XmlFormat>> for TileOffset<JsonFormat> {
+    fn from(offset: TileOffset<XmlFormat>) -> Self {
+        TileOffset::<JsonFormat> {
+            x: offset.x,
+            y: offset.y,
+            rest: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Grid<T: SerializationFormat> {
+    #[serde(rename = "@orientation", default)]
+    orientation: String,
+    #[serde(rename = "@width")]
+    width: u32,
+    #[serde(rename = "@height")]
+    height: u32,
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+impl<T> Serialize for Grid<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("grid", 3)?;
+        res.serialize_field(T::transform_name("@orientation"), &self.orientation)?;
+        res.serialize_field(T::transform_name("@width"), &self.width)?;
+        res.serialize_field(T::transform_name("@height"), &self.height)?;
+        res.end()
+    }
+}
+
+impl From<Grid<XmlFormat>> for Grid<JsonFormat> {
+    fn from(grid: Grid<XmlFormat>) -> Self {
+        Grid::<JsonFormat> {
+            orientation: grid.orientation,
+            width: grid.width,
+            height: grid.height,
+            rest: Default::default(),
+        }
+    }
+}
+
+/// Which ways tiles of the set may be flipped or rotated when placed
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Transformations<T: SerializationFormat> {
+    #[serde(rename = "@hflip", default)]
+    hflip: bool,
+    #[serde(rename = "@vflip", default)]
+    vflip: bool,
+    #[serde(rename = "@rotate", default)]
+    rotate: bool,
+    #[serde(rename = "@preferuntransformed", default)]
+    preferuntransformed: bool,
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+impl<T> Serialize for Transformations<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("transformations", 4)?;
+        res.serialize_field(T::transform_name("@hflip"), &T::transform_flag(self.hflip))?;
+        res.serialize_field(T::transform_name("@vflip"), &T::transform_flag(self.vflip))?;
+        res.serialize_field(
+            T::transform_name("@rotate"),
+            &T::transform_flag(self.rotate),
+        )?;
+        res.serialize_field(
+            T::transform_name("@preferuntransformed"),
+            &T::transform_flag(self.preferuntransformed),
+        )?;
+        res.end()
+    }
+}
+
+impl From<Transformations<XmlFormat>> for Transformations<JsonFormat> {
+    fn from(transformations: Transformations<XmlFormat>) -> Self {
+        Transformations::<JsonFormat> {
+            hflip: transformations.hflip,
+            vflip: transformations.vflip,
+            rotate: transformations.rotate,
+            preferuntransformed: transformations.preferuntransformed,
+            rest: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct TileSet<T: SerializationFormat> {
+    #[serde(rename = "@firstgid", default)]
+    firstgid: u32,
+    #[serde(rename = "@source")]
+    source: Option<String>,
+    #[serde(rename = "@name", default)]
+    name: String,
+    #[serde(rename = "@tilewidth", default)]
+    tilewidth: u32,
+    #[serde(rename = "@tileheight", default)]
+    tileheight: u32,
+    #[serde(rename = "@spacing", default)]
+    spacing: u32,
+    #[serde(rename = "@margin", default)]
+    margin: u32,
+    #[serde(rename = "@tilecount", default)]
+    tilecount: u32,
+    #[serde(rename = "@columns", default)]
+    columns: u32,
+    #[serde(rename = "@objectalignment")]
+    objectalignment: Option<String>,
+    #[serde(rename = "@tilerendersize")]
+    tilerendersize: Option<String>,
+    #[serde(rename = "@fillmode")]
+    fillmode: Option<String>,
+    tileoffset: Option<TileOffset<T>>,
+    grid: Option<Grid<T>>,
+    transformations: Option<Transformations<T>>,
+    image: Option<Image<T>>,
+    #[serde(rename = "tile", default)]
+    tiles: Vec<Tile<T>>,
+    wangsets: Option<WangSets<T>>,
+}
+
+impl From<TileSet<XmlFormat>> for TileSet<JsonFormat> {
+    fn from(tileset: TileSet<XmlFormat>) -> Self {
+        TileSet::<JsonFormat> {
+            firstgid: tileset.firstgid,
+            source: tileset.source,
+            name: tileset.name,
+            tilewidth: tileset.tilewidth,
+            tileheight: tileset.tileheight,
+            spacing: tileset.spacing,
+            margin: tileset.margin,
+            tilecount: tileset.tilecount,
+            columns: tileset.columns,
+            objectalignment: tileset.objectalignment,
+            tilerendersize: tileset.tilerendersize,
+            fillmode: tileset.fillmode,
+            tileoffset: tileset.tileoffset.map(|x| x.into()),
+            grid: tileset.grid.map(|x| x.into()),
+            transformations: tileset.transformations.map(|x| x.into()),
+            image: tileset.image.map(|x| x.into()),
+            tiles: tileset.tiles.into_iter().map(|x| x.into()).collect(),
+            wangsets: tileset.wangsets.map(|x| x.into()),
+        }
+    }
+}
+
+impl<T> Serialize for TileSet<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_map(Some(7))?;
+        res.serialize_entry(T::transform_name("@firstgid"), &self.firstgid)?;
+        if let Some(source) = &self.source {
+            res.serialize_entry(T::transform_name("@source"), source)?;
+            return res.end();
+        }
+        res.serialize_entry(T::transform_name("@name"), &self.name)?;
+        res.serialize_entry(T::transform_name("@tilewidth"), &self.tilewidth)?;
+        res.serialize_entry(T::transform_name("@tileheight"), &self.tileheight)?;
+        if self.spacing != 0 {
+            res.serialize_entry(T::transform_name("@spacing"), &self.spacing)?;
+        }
+        if self.margin != 0 {
+            res.serialize_entry(T::transform_name("@margin"), &self.margin)?;
+        }
+        res.serialize_entry(T::transform_name("@tilecount"), &self.tilecount)?;
+        res.serialize_entry(T::transform_name("@columns"), &self.columns)?;
+        if let Some(objectalignment) = &self.objectalignment {
+            res.serialize_entry(T::transform_name("@objectalignment"), objectalignment)?;
+        }
+        if let Some(tilerendersize) = &self.tilerendersize {
+            res.serialize_entry(T::transform_name("@tilerendersize"), tilerendersize)?;
+        }
+        if let Some(fillmode) = &self.fillmode {
+            res.serialize_entry(T::transform_name("@fillmode"), fillmode)?;
+        }
+        if let Some(tileoffset) = &self.tileoffset {
+            res.serialize_entry("tileoffset", tileoffset)?;
+        }
+        if let Some(grid) = &self.grid {
+            res.serialize_entry("grid", grid)?;
+        }
+        if let Some(transformations) = &self.transformations {
+            res.serialize_entry("transformations", transformations)?;
+        }
+        if let Some(image) = &self.image {
+            for (k, v) in T::transform_image(image).into_iter() {
+                res.serialize_entry(&k, &v)?;
+            }
+        }
+        if !self.tiles.is_empty() {
+            res.serialize_entry(T::transform_vec_name("tiles"), &self.tiles)?;
+        }
+        if let Some(wangsets) = &self.wangsets {
+            res.serialize_entry("wangsets", wangsets)?;
+        }
+        res.end()
+    }
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct DataField<T: SerializationFormat>(Vec<Vec<u32>>, PhantomData<T>);
+
+impl From<DataField<XmlFormat>> for DataField<JsonFormat> {
+    fn from(data: DataField<XmlFormat>) -> Self {
+        DataField::<JsonFormat>(data.0, Default::default())
+    }
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat", try_from = "RawData")]
+struct Data<T: SerializationFormat> {
+    encoding: String,
+    data: DataField<T>,
+}
+
+/// `<data>` as stored in the file, before decoding
+#[derive(Deserialize)]
+struct RawData {
+    #[serde(rename = "@encoding")]
+    encoding: String,
+    #[serde(rename = "@compression")]
+    compression: Option<String>,
+    #[serde(rename = "$text")]
+    text: String,
+}
+
+/// Keeps the rows of CSV data, other encodings are decoded into one row
+/// that `read_map` splits by the width of the layer
+impl<T> TryFrom<RawData> for Data<T>
+where
+    T: SerializationFormat,
+{
+    type Error = String;
+
+    fn try_from(raw: RawData) -> Result<Self, Self::Error> {
+        let rows = if raw.encoding == "csv" {
+            raw.text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| textconv::decode(line, Some("csv"), None))
+                .collect::<Result<_, _>>()?
+        } else {
+            let gids =
+                textconv::decode(&raw.text, Some(&raw.encoding), raw.compression.as_deref())?;
+            vec![gids]
+        };
+        Ok(Data {
+            encoding: raw.encoding,
+            data: DataField(rows, Default::default()),
+        })
+    }
+}
+
+impl<T> Serialize for Data<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_data(self, serializer)
+    }
+}
+
+impl From<Data<XmlFormat>> for Data<JsonFormat> {
+    fn from(data: Data<XmlFormat>) -> Self {
+        Data::<JsonFormat> {
+            encoding: data.encoding,
+            data: data.data.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Marker<T: SerializationFormat> {
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+impl<T> Serialize for Marker<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_marker(serializer)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Points<T: SerializationFormat> {
+    #[serde(rename = "@points", deserialize_with = "deserialize_points")]
+    points: Vec<(f64, f64)>,
+    #[serde(skip)]
+    rest: PhantomData<T>,
+}
+
+fn deserialize_points<'de, D>(deserializer: D) -> Result<Vec<(f64, f64)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split_whitespace()
+        .map(|point| {
+            let (x, y) = point
+                .split_once(',')
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid point {point}")))?;
+            let x = x.parse::<f64>().map_err(serde::de::Error::custom)?;
+            let y = y.parse::<f64>().map_err(serde::de::Error::custom)?;
+            Ok((x, y))
+        })
+        .collect()
+}
+
+impl<T> Serialize for Points<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        T::serialize_points(&self.points, serializer)
+    }
+}
+
+impl From<Points<XmlFormat>> for Points<JsonFormat> {
+    fn from(points: Points<XmlFormat>) -> Self {
+        Points::<JsonFormat> {
+            points: points.points,
+            rest: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+struct Object<T: SerializationFormat> {
+    #[serde(rename = "@id")]
+    id: u32,
+    #[serde(rename = "@name", default)]
+    name: String,
+    #[serde(rename = "@type", default)]
+    kind: String,
+    #[serde(rename = "@gid")]
+    gid: Option<u32>,
+    #[serde(rename = "@x", default)]
+    x: f64,
+    #[serde(rename = "@y", default)]
+    y: f64,
+    #[serde(rename = "@width")]
+    width: Option<f64>,
+    #[serde(rename = "@height")]
+    height: Option<f64>,
+    #[serde(rename = "@rotation")]
+    rotation: Option<f64>,
+    #[serde(rename = "@visible")]
+    visible: Option<u32>,
+    #[serde(rename = "@template")]
+    template: Option<String>,
+    properties: Option<Properties<T>>,
+    ellipse: Option<Marker<T>>,
+    point: Option<Marker<T>>,
+    polygon: Option<Points<T>>,
+    polyline: Option<Points<T>>,
+}
+
+impl<T> Serialize for Object<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut res = serializer.serialize_struct("object", 16)?;
+        res.serialize_field(T::transform_name("@id"), &self.id)?;
+        if !self.name.is_empty() {
+            res.serialize_field(T::transform_name("@name"), &self.name)?;
+        }
+        if !self.kind.is_empty() {
+            res.serialize_field(T::transform_name("@type"), &self.kind)?;
+        }
+        if let Some(gid) = &self.gid {
+            res.serialize_field(T::transform_name("@gid"), gid)?;
+        }
+        res.serialize_field(T::transform_name("@x"), &self.x)?;
+        res.serialize_field(T::transform_name("@y"), &self.y)?;
+        if let Some(width) = &self.width {
+            res.serialize_field(T::transform_name("@width"), width)?;
+        }
+        if let Some(height) = &self.height {
+            res.serialize_field(T::transform_name("@height"), height)?;
+        }
+        if let Some(rotation) = &self.rotation {
+            res.serialize_field(T::transform_name("@rotation"), rotation)?;
+        }
+        if let Some(visible) = &self.visible {
+            res.serialize_field(T::transform_name("@visible"), visible)?;
+        }
+        if let Some(template) = &self.template {
+            res.serialize_field(T::transform_name("@template"), template)?;
+        }
+        if let Some(properties) = &self.properties {
+            res.serialize_field("properties", properties)?;
+        }
+        if let Some(ellipse) = &self.ellipse {
+            res.serialize_field("ellipse", ellipse)?;
+        }
+        if let Some(point) = &self.point {
+            res.serialize_field("point", point)?;
+        }
+        if let Some(polygon) = &self.polygon {
+            res.serialize_field("polygon", polygon)?;
+        }
+        if let Some(polyline) = &self.polyline {
+            res.serialize_field("polyline", polyline)?;
+        }
+        res.end()
+    }
+}
+
+impl From<Object<XmlFormat>> for Object<JsonFormat> {
+    fn from(object: Object<XmlFormat>) -> Self {
+        Object::<JsonFormat> {
+            id: object.id,
+            name: object.name,
+            kind: object.kind,
+            gid: object.gid,
+            x: object.x,
+            y: object.y,
+            width: object.width,
+            height: object.height,
+            rotation: object.rotation,
+            visible: object.visible,
+            template: object.template,
+            properties: object.properties.map(|x| x.into()),
+            ellipse: object.ellipse.map(|_| Marker {
+                rest: Default::default(),
+            }),
+            point: object.point.map(|_| Marker {
+                rest: Default::default(),
+            }),
+            polygon: object.polygon.map(|x| x.into()),
+            polyline: object.polyline.map(|x| x.into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(bound = "T: SerializationFormat")]
+enum LayerType<T: SerializationFormat> {
+    #[serde(rename = "layer")]
+    Layer(Layer<T>),
+    #[serde(rename = "imagelayer")]
+    ImageLayer(Layer<T>),
+    #[serde(rename = "group")]
+    Group(Layer<T>),
+    #[serde(rename = "objectgroup")]
+    ObjectGroup(Layer<T>),
+    /// Elements that aren't modeled yet, like properties
+    #[serde(other)]
+    Unknown,
+}
+
+impl<T> LayerType<T>
+where
+    T: SerializationFormat,
+{
+    fn layer(&self) -> Option<&Layer<T>> {
+        use LayerType::*;
+        match self {
+            Layer(layer) | ImageLayer(layer) | Group(layer) | ObjectGroup(layer) => Some(layer),
+            Unknown => None,
+        }
+    }
+
+    fn layer_mut(&mut self) -> Option<&mut Layer<T>> {
+        use LayerType::*;
+        match self {
+            Layer(layer) | ImageLayer(layer) | Group(layer) | ObjectGroup(layer) => Some(layer),
+            Unknown => None,
+        }
+    }
+}
+
+impl<T> Serialize for LayerType<T>
+where
+    T: SerializationFormat,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let Some(layer) = self.layer() else {
+            return serializer.serialize_unit();
+        };
+
+        let mut res = serializer.serialize_struct("layer", 8)?;
+        if let Some(layer_type) = T::layer_type(self) {
+            res.serialize_field(T::transform_name("@type"), layer_type)?;
+        }
+        if let Some(id) = &layer.id {
+            res.serialize_field(T::transform_name("@id"), id)?;
+        }
+        res.serialize_field(T::transform_name("@name"), &layer.name)?;
+        if let Some(width) = &layer.width {
+            res.serialize_field(T::transform_name("@width"), width)?;
         }
         if let Some(height) = &layer.height {
             res.serialize_field(T::transform_name("@height"), height)?;
@@ -486,9 +1790,22 @@ where
         if let Some(offsety) = &layer.offsety {
             res.serialize_field(T::transform_name("@offsety"), offsety)?;
         }
+        if let Some(visible) = layer.visible {
+            res.serialize_field(
+                T::transform_name("@visible"),
+                &T::transform_flag(visible != 0),
+            )?;
+        }
         if let Some(data) = &layer.data {
             res.serialize_field("data", data)?;
         }
+        match self {
+            LayerType::Group(_) => T::transform_layers(&layer.layers, &mut res),
+            LayerType::ObjectGroup(_) => {
+                res.serialize_field(T::transform_vec_name("objects"), &layer.objects)?
+            }
+            _ => (),
+        }
         res.end()
     }
 }
@@ -501,11 +1818,12 @@ impl From<LayerType<XmlFormat>> for LayerType<JsonFormat> {
             ImageLayer(layer) => ImageLayer(layer.into()),
             Group(layer) => Group(layer.into()),
             ObjectGroup(layer) => ObjectGroup(layer.into()),
+            Unknown => Unknown,
         }
     }
 }
 
-#[derive(Default, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Default, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
 struct Layer<T: SerializationFormat> {
     #[serde(rename = "@id")]
@@ -517,19 +1835,21 @@ struct Layer<T: SerializationFormat> {
     #[serde(rename = "@height")]
     height: Option<u32>,
     #[serde(rename = "@offsetx", default)]
-    offsetx: Option<u32>,
+    offsetx: Option<f64>,
     #[serde(rename = "@offsety", default)]
-    offsety: Option<u32>,
+    offsety: Option<f64>,
+    #[serde(rename = "@visible")]
+    visible: Option<u32>,
     data: Option<Data<T>>,
+    #[serde(rename = "object", default)]
+    objects: Vec<Object<T>>,
+    #[serde(rename = "$value", default)]
+    layers: Vec<LayerType<T>>,
 }
 
 impl From<Layer<XmlFormat>> for Layer<JsonFormat> {
     fn from(layer: Layer<XmlFormat>) -> Self {
-        let data = if let Some(data) = layer.data {
-            Some(data.into())
-        } else {
-            None
-        };
+        let data = layer.data.map(|data| data.into());
         Layer::<JsonFormat> {
             id: layer.id,
             name: layer.name,
@@ -537,12 +1857,15 @@ impl From<Layer<XmlFormat>> for Layer<JsonFormat> {
             height: layer.height,
             offsetx: layer.offsetx,
             offsety: layer.offsety,
+            visible: layer.visible,
             data,
+            objects: layer.objects.into_iter().map(|x| x.into()).collect(),
+            layers: layer.layers.into_iter().map(|x| x.into()).collect(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(bound = "T: SerializationFormat")]
 struct Map<T: SerializationFormat> {
     #[serde(rename = "@version")]
@@ -570,7 +1893,8 @@ struct Map<T: SerializationFormat> {
     #[serde(rename = "@nextobjectid")]
     nextobjectid: u32,
     editorsettings: Option<EditorSettings<T>>,
-    #[serde(rename = "tileset")]
+    properties: Option<Properties<T>>,
+    #[serde(rename = "tileset", default)]
     tilesets: Vec<TileSet<T>>,
     #[serde(rename = "$value")]
     layers: Vec<LayerType<T>>,
@@ -598,7 +1922,9 @@ where
         if let Some(infinite) = &self.infinite {
             res.serialize_field(T::transform_name("@infinite"), infinite)?;
         }
-        res.serialize_field(T::transform_name("@backgroundcolor"), &self.backgroundcolor)?;
+        if !self.backgroundcolor.is_empty() {
+            res.serialize_field(T::transform_name("@backgroundcolor"), &self.backgroundcolor)?;
+        }
         if let Some(nextlayerid) = &self.nextlayerid {
             res.serialize_field(T::transform_name("@nextlayerid"), nextlayerid)?;
         }
@@ -606,6 +1932,9 @@ where
         if let Some(editorsettings) = &self.editorsettings {
             res.serialize_field("editorsettings", editorsettings)?;
         }
+        if let Some(properties) = &self.properties {
+            res.serialize_field("properties", properties)?;
+        }
         res.serialize_field(T::transform_vec_name("tilesets"), &self.tilesets)?;
         T::transform_layers(&self.layers, &mut res);
         res.end()
@@ -614,11 +1943,9 @@ where
 
 impl From<Map<XmlFormat>> for Map<JsonFormat> {
     fn from(map: Map<XmlFormat>) -> Self {
-        let editorsettings = if let Some(editorsettings) = map.editorsettings {
-            Some(editorsettings.into())
-        } else {
-            None
-        };
+        let editorsettings = map
+            .editorsettings
+            .map(|editorsettings| editorsettings.into());
         let tilesets = map.tilesets.into_iter().map(|x| x.into()).collect();
         let layers = map.layers.into_iter().map(|x| x.into()).collect();
         Map::<JsonFormat> {
@@ -635,79 +1962,469 @@ impl From<Map<XmlFormat>> for Map<JsonFormat> {
             nextlayerid: map.nextlayerid,
             nextobjectid: map.nextobjectid,
             editorsettings,
+            properties: map.properties.map(|x| x.into()),
             tilesets,
             layers,
         }
     }
 }
 
+fn find_layer_mut<'a, T>(layers: &'a mut [LayerType<T>], name: &str) -> Option<&'a mut Layer<T>>
+where
+    T: SerializationFormat,
+{
+    layers.iter_mut().find_map(|layer| match layer {
+        LayerType::Layer(layer) if layer.name == name => Some(layer),
+        LayerType::Group(group) => find_layer_mut(&mut group.layers, name),
+        _ => None,
+    })
+}
+
+/// All layers in depth-first order with their `group/layer` paths
+fn all_layers<T>(layers: &[LayerType<T>]) -> Vec<(String, &LayerType<T>)>
+where
+    T: SerializationFormat,
+{
+    let mut res = Vec::new();
+    for layer_type in layers {
+        if let Some(layer) = layer_type.layer() {
+            res.push((layer.name.clone(), layer_type));
+            for (path, inner) in all_layers(&layer.layers) {
+                res.push((format!("{}/{}", layer.name, path), inner));
+            }
+        }
+    }
+    res
+}
+
+fn for_each_cell<T>(layers: &mut [LayerType<T>], f: &mut impl FnMut(&mut u32))
+where
+    T: SerializationFormat,
+{
+    for layer in layers.iter_mut().filter_map(|x| x.layer_mut()) {
+        if let Some(data) = &mut layer.data {
+            for row in &mut data.data.0.iter_mut() {
+                for cell in row.iter_mut() {
+                    f(cell);
+                }
+            }
+        }
+        for_each_cell(&mut layer.layers, f);
+    }
+}
+
+fn for_each_layer<T>(layers: &mut [LayerType<T>], f: &mut impl FnMut(&mut Layer<T>))
+where
+    T: SerializationFormat,
+{
+    for layer in layers.iter_mut().filter_map(|x| x.layer_mut()) {
+        f(layer);
+        for_each_layer(&mut layer.layers, f);
+    }
+}
+
+fn for_each_object<T>(layers: &mut [LayerType<T>], f: &mut impl FnMut(&mut Object<T>))
+where
+    T: SerializationFormat,
+{
+    for layer in layers.iter_mut().filter_map(|x| x.layer_mut()) {
+        layer.objects.iter_mut().for_each(&mut *f);
+        for_each_object(&mut layer.layers, f);
+    }
+}
+
+/// Next free layer and object ids, never below `nextlayerid` and `nextobjectid`.
+/// The layer id stays unset for maps without layer ids.
+fn next_ids<T>(map: &mut Map<T>) -> (Option<u32>, u32)
+where
+    T: SerializationFormat,
+{
+    let mut layer = map.nextlayerid;
+    let mut object = map.nextobjectid;
+    for_each_layer(&mut map.layers, &mut |x| {
+        if let (Some(next), Some(id)) = (&mut layer, x.id) {
+            *next = (*next).max(id + 1);
+        }
+        for o in &x.objects {
+            object = object.max(o.id + 1);
+        }
+    });
+    (layer, object)
+}
+
+/// Rewrites every tile reference in layers and tile objects, keeping flip flags.
+/// `f` gets a non-empty gid without flags and returns the new one, 0 clears it.
+fn remap_gids<T>(map: &mut Map<T>, mut f: impl FnMut(u32) -> u32)
+where
+    T: SerializationFormat,
+{
+    let mut remap = |gid: &mut u32| {
+        if *gid & GID_MASK != 0 {
+            let new = f(*gid & GID_MASK);
+            *gid = if new == 0 {
+                0
+            } else {
+                new | (*gid & !GID_MASK)
+            };
+        }
+    };
+    for_each_cell(&mut map.layers, &mut remap);
+    for_each_object(&mut map.layers, &mut |object| {
+        if let Some(gid) = &mut object.gid {
+            remap(gid);
+        }
+    });
+}
+
+fn tileset_for_gid<T>(tilesets: &[TileSet<T>], gid: u32) -> Option<&TileSet<T>>
+where
+    T: SerializationFormat,
+{
+    tilesets
+        .iter()
+        .filter(|tileset| tileset.firstgid <= gid & GID_MASK)
+        .max_by_key(|tileset| tileset.firstgid)
+}
+
+fn read_map(path: &Path) -> Map<XmlFormat> {
+    try_read_map(path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+fn try_read_map(path: &Path) -> Result<Map<XmlFormat>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut map: Map<XmlFormat> = from_str(&contents).map_err(|err| err.to_string())?;
+    let width = map.width;
+    for_each_layer(&mut map.layers, &mut |layer| {
+        let width = layer.width.unwrap_or(width) as usize;
+        if let Some(data) = layer.data.as_mut().filter(|data| data.encoding != "csv") {
+            let gids = data.data.0.concat();
+            data.data.0 = gids.chunks(width.max(1)).map(<[u32]>::to_vec).collect();
+            data.encoding = "csv".into();
+        }
+    });
+    let dir = path.parent().unwrap_or(Path::new(""));
+    load_external_tilesets(&mut map, dir)?;
+    Ok(map)
+}
+
+/// Fills in tilesets stored in .tsx files, keeping the reference so that
+/// they are written back as external tilesets. Missing files only get a
+/// warning, files that can't be parsed are an error.
+fn load_external_tilesets(map: &mut Map<XmlFormat>, dir: &Path) -> Result<(), String> {
+    for tileset in &mut map.tilesets {
+        let Some(source) = &tileset.source else {
+            continue;
+        };
+        let contents = match fs::read_to_string(dir.join(source)) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("Can't read tileset {source}, its tiles are unknown: {err}");
+                continue;
+            }
+        };
+        let mut loaded: TileSet<XmlFormat> =
+            from_str(&contents).map_err(|err| format!("{source}: {err}"))?;
+        loaded.firstgid = tileset.firstgid;
+        loaded.source = tileset.source.take();
+        *tileset = loaded;
+    }
+    Ok(())
+}
+
+fn to_xml<T>(map: &Map<T>) -> String
+where
+    T: SerializationFormat,
+{
+    let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 1);
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .expect("cannot write xml header");
+    writer
+        .write_serializable("map", map)
+        .expect("cannot serialize map");
+    let xml = writer.into_inner().into_inner();
+    String::from_utf8_lossy(&xml).into_owned()
+}
+
+fn print_xml<T>(map: &Map<T>)
+where
+    T: SerializationFormat,
+{
+    println!("{}", to_xml(map));
+}
+
+fn write_xml<T>(path: &Path, map: &Map<T>)
+where
+    T: SerializationFormat,
+{
+    fs::write(path, to_xml(map) + "\n")
+        .unwrap_or_else(|err| panic!("Can't write {}: {err}", path.display()));
+}
+
 fn main() {
     let cli = Cli::parse();
 
-    let contents = fs::read_to_string(cli.file).expect("Should have been able to read the file");
-    let mut map: Map<XmlFormat> = match from_str(&contents) {
-        Ok(map) => map,
-        Err(err) => panic!("{:?}", err),
-    };
+    // Works on the raw file, which may use encodings the model can't read
+    if cli.command == Commands::Textconv {
+        textconv::textconv(&cli.file);
+        return;
+    }
 
-    if cli.command == Commands::Convert {
-        let map: Map<JsonFormat> = map.into();
-        let res = serde_json::to_string_pretty(&map).unwrap();
-        println!("{res}");
-    } else {
-        let tileset = map
-            .tilesets
-            .iter_mut()
-            .next()
-            .expect("Needs at least one tileset");
-        for layer in &mut map.layers {
-            use LayerType::*;
-            let layer = match layer {
-                Layer(layer) => layer,
-                ImageLayer(layer) => layer,
-                Group(layer) => layer,
-                ObjectGroup(layer) => layer,
-            };
-            if let Some(data) = &mut layer.data {
-                for row in &mut data.data.0.iter_mut() {
-                    for cell in row.iter_mut() {
-                        match cli.command {
-                            Commands::Replace { find, replace } => {
-                                if *cell != 0 && *cell - 1 == find {
-                                    *cell = replace + 1;
-                                }
-                            }
-                            Commands::Resize { columns, .. } => {
-                                if *cell >= tileset.columns {
-                                    let diff = (*cell - 1) as i32 / tileset.columns as i32
-                                        * (columns as i32 - tileset.columns as i32);
-                                    if diff.is_negative() {
-                                        *cell -= diff.wrapping_abs() as u32;
-                                    } else {
-                                        *cell += diff as u32;
-                                    };
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
+    // Reports maps that can't be read as a problem instead of panicking
+    if let Commands::Validate { format } = cli.command {
+        if validate::validate(try_read_map(&cli.file).as_ref(), &cli.file, format) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut map = read_map(&cli.file);
+
+    match cli.command {
+        Commands::Convert => {
+            let map: Map<JsonFormat> = map.into();
+            let res = serde_json::to_string_pretty(&map).unwrap();
+            println!("{res}");
+        }
+        Commands::Replace { find, replace } => {
+            for_each_cell(&mut map.layers, &mut |cell| {
+                if *cell != 0 && *cell - 1 == find {
+                    *cell = replace + 1;
                 }
-            }
+            });
+            print_xml(&map);
         }
-        if let Commands::Resize { tilecount, columns } = cli.command {
+        Commands::Resize {
+            columns,
+            tilecount,
+            image,
+        } => {
+            let tileset = map
+                .tilesets
+                .iter_mut()
+                .next()
+                .expect("Needs at least one tileset");
+            assert!(
+                tileset.source.is_none(),
+                "Tileset {} is stored in a .tsx file, embed it first",
+                tileset.name
+            );
+            let old_columns = tileset.columns;
+            if let Some(image) = image {
+                let dir = cli.file.parent().unwrap_or(Path::new(""));
+                atlas::relayout(tileset, dir, columns, tilecount, &image, |id| {
+                    (id % old_columns < columns)
+                        .then(|| id / old_columns * columns + id % old_columns)
+                });
+            }
             tileset.columns = columns;
             tileset.tilecount = tilecount;
+            for_each_cell(&mut map.layers, &mut |cell| {
+                if *cell >= old_columns {
+                    let diff = (*cell - 1) as i32 / old_columns as i32
+                        * (columns as i32 - old_columns as i32);
+                    if diff.is_negative() {
+                        *cell -= diff.wrapping_abs() as u32;
+                    } else {
+                        *cell += diff as u32;
+                    };
+                }
+            });
+            print_xml(&map);
+        }
+        Commands::Autotile {
+            layer,
+            wangset,
+            region,
+        } => {
+            autotile::autotile(&mut map, &layer, &wangset, region.as_ref());
+            print_xml(&map);
+        }
+        Commands::Fill {
+            layer,
+            x,
+            y,
+            replace,
+            diagonal,
+        } => {
+            fill::fill(&mut map, &layer, x, y, replace, diagonal);
+            print_xml(&map);
+        }
+        Commands::Stats { format } => stats::stats(&map, format),
+        Commands::Find {
+            tile,
+            tileset,
+            format,
+        } => find::find(&map, tile, tileset.as_deref(), format),
+        Commands::MergeTilesets { into, from, image } => {
+            let dir = cli.file.parent().unwrap_or(Path::new(""));
+            tileset::merge(&mut map, dir, &into, &from, image.as_deref());
+            print_xml(&map);
+        }
+        Commands::Reorder {
+            tileset,
+            permutation,
+        } => {
+            let permutation = tileset::read_permutation(&permutation);
+            tileset::reorder(&mut map, &tileset, &permutation);
+            print_xml(&map);
+        }
+        Commands::RemoveTileset { tileset, fallback } => {
+            tileset::remove(&mut map, &tileset, fallback);
+            print_xml(&map);
+        }
+        Commands::Unused {
+            tileset,
+            maps,
+            format,
+        } => {
+            let mut maps = maps
+                .into_iter()
+                .map(|path| {
+                    let map = read_map(&path);
+                    (path, map)
+                })
+                .collect::<Vec<_>>();
+            maps.insert(0, (cli.file, map));
+            unused::unused(&maps, &tileset, format);
+        }
+        Commands::ResizeMap {
+            width,
+            height,
+            anchor,
+            offset,
+            remove_outside,
+        } => {
+            let offset =
+                offset.unwrap_or_else(|| canvas::anchor_offset(&map, width, height, anchor));
+            canvas::resize_map(&mut map, width, height, offset, remove_outside);
+            print_xml(&map);
+        }
+        Commands::Stitch { maps } => {
+            let dir = cli.file.parent().unwrap_or(Path::new(""));
+            let maps = maps
+                .into_iter()
+                .map(|placement| {
+                    let other = read_map(&placement.path);
+                    (placement, other)
+                })
+                .collect();
+            stitch::stitch(&mut map, dir, maps);
+            print_xml(&map);
+        }
+        Commands::Layer { layer, action } => {
+            layers::layer(&mut map, &layer, action);
+            print_xml(&map);
+        }
+        Commands::MergeLayers {
+            layers,
+            name,
+            drop_hidden,
+        } => {
+            flatten::merge_layers(&mut map, &layers, name, drop_hidden);
+            print_xml(&map);
+        }
+        Commands::Extract { layer, region } => {
+            let res = extract::extract(&map, &layer, region.as_ref());
+            print_xml(&res);
+        }
+        Commands::Split {
+            width,
+            height,
+            output,
+            format,
+        } => {
+            split::split(&map, &cli.file, width, height, &output, format);
+        }
+        Commands::Paste {
+            stamp,
+            x,
+            y,
+            skip_empty,
+        } => {
+            let dir = cli.file.parent().unwrap_or(Path::new(""));
+            let other = read_map(&stamp);
+            stitch::paste(&mut map, dir, &stamp, other, Offset { x, y }, skip_empty);
+            print_xml(&map);
+        }
+        Commands::Diff { other, format } => {
+            let other = read_map(&other);
+            if diff::diff(&map, &other, format) {
+                std::process::exit(1);
+            }
+        }
+        Commands::MergeDriver {
+            ours,
+            theirs,
+            report,
+        } => {
+            // Writing the result must not drop what the model can't represent,
+            // git falls back to a conflict on the whole file instead
+            let mut merged = read_map(&ours);
+            let other = read_map(&theirs);
+            for (path, map) in [(&cli.file, &map), (&ours, &merged), (&theirs, &other)] {
+                let contents =
+                    fs::read_to_string(path).expect("Should have been able to read the file");
+                if let Err(err) = merge::lossless(&contents, map) {
+                    eprintln!("{}: {err}, merge it by hand", path.display());
+                    std::process::exit(1);
+                }
+            }
+            let conflicts = merge::merge(&map, &mut merged, &other);
+            write_xml(&ours, &merged);
+            if !conflicts.is_empty() {
+                let report_text = merge::report(&conflicts);
+                match report {
+                    Some(path) => fs::write(&path, report_text)
+                        .unwrap_or_else(|err| panic!("Can't write {}: {err}", path.display())),
+                    None => eprint!("{report_text}"),
+                }
+                std::process::exit(1);
+            }
+        }
+        Commands::Lint { config, format } => {
+            let config = lint::read_config(&config);
+            if lint::lint(&map, &cli.file, &config, format) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Textconv | Commands::Validate { .. } => {
+            unreachable!("Handled before reading the map")
+        }
+        Commands::Transform { transformation } => {
+            transform::transform(&mut map, transformation);
+            print_xml(&map);
+        }
+        Commands::SetTileSize {
+            tilewidth,
+            tileheight,
+            tileset,
+        } => {
+            let dir = cli.file.parent().unwrap_or(Path::new(""));
+            tilesize::set_tile_size(&mut map, dir, tilewidth, tileheight, &tileset);
+            print_xml(&map);
+        }
+        Commands::Dedupe {
+            tileset,
+            maps,
+            remap,
+            format,
+        } => {
+            assert!(
+                !remap || cli.in_place,
+                "--remap rewrites the maps, pass --in-place to allow that"
+            );
+            let mut maps = maps
+                .into_iter()
+                .map(|path| {
+                    let map = read_map(&path);
+                    (path, map)
+                })
+                .collect::<Vec<_>>();
+            maps.insert(0, (cli.file, map));
+            for (path, map) in dedupe::dedupe(maps, &tileset, remap, format) {
+                write_xml(&path, &map);
+            }
         }
-
-        let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 1);
-        writer
-            .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
-            .expect("cannot write xml header");
-        writer
-            .write_serializable("map", &map)
-            .expect("cannot serialize map");
-        let xml = writer.into_inner().into_inner();
-        let xml_str = String::from_utf8_lossy(&xml);
-        println!("{}", xml_str);
     }
 }
@@ -0,0 +1,478 @@
+use crate::textconv::normalize;
+use crate::{
+    all_layers, for_each_layer, next_ids, to_xml, Layer, LayerType, Map, Object, XmlFormat,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Something both sides changed in different ways, the merged map keeps ours
+pub struct Conflict {
+    what: String,
+    base: String,
+    ours: String,
+    theirs: String,
+}
+
+/// Formats conflicts like git does with `merge.conflictStyle = diff3`
+pub fn report(conflicts: &[Conflict]) -> String {
+    let mut res = String::new();
+    for conflict in conflicts {
+        let _ = writeln!(res, "{}", conflict.what);
+        let _ = writeln!(res, "<<<<<<< ours\n{}", conflict.ours);
+        let _ = writeln!(res, "||||||| base\n{}", conflict.base);
+        let _ = writeln!(res, "=======\n{}", conflict.theirs);
+        let _ = writeln!(res, ">>>>>>> theirs");
+    }
+    res
+}
+
+/// Checks that writing `map`, read from `contents`, keeps everything in it.
+/// Returns the first line of the normalized file that would change otherwise.
+pub fn lossless(contents: &str, map: &Map<XmlFormat>) -> Result<(), String> {
+    let before = normalize(contents, true)?;
+    let after = normalize(&to_xml(map), true)?;
+    let mut after_lines = after.lines();
+    for line in before.lines() {
+        if after_lines.next() != Some(line) {
+            return Err(format!("can't keep `{}`", line.trim()));
+        }
+    }
+    match after_lines.next() {
+        Some(line) => Err(format!("would add `{}`", line.trim())),
+        None => Ok(()),
+    }
+}
+
+type Layers = Vec<LayerType<XmlFormat>>;
+
+fn layer_at<'a>(layers: &'a mut Layers, path: &str) -> Option<&'a mut Layer<XmlFormat>> {
+    let (name, rest) = match path.split_once('/') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let layer = layers
+        .iter_mut()
+        .filter_map(|x| x.layer_mut())
+        .find(|layer| layer.name == name)?;
+    match rest {
+        Some(rest) => layer_at(&mut layer.layers, rest),
+        None => Some(layer),
+    }
+}
+
+/// Layers around `path`, the top-level ones for top-level layers
+fn parent_at<'a>(layers: &'a mut Layers, path: &str) -> Option<&'a mut Layers> {
+    match path.rsplit_once('/') {
+        Some((parent, _)) => layer_at(layers, parent).map(|layer| &mut layer.layers),
+        None => Some(layers),
+    }
+}
+
+fn find<'a>(
+    layers: &[(String, &'a LayerType<XmlFormat>)],
+    path: &str,
+) -> Option<&'a Layer<XmlFormat>> {
+    layers
+        .iter()
+        .find(|(other, _)| other == path)
+        .and_then(|(_, layer_type)| layer_type.layer())
+}
+
+fn describe_object(object: Option<&(String, &Object<XmlFormat>)>) -> String {
+    match object {
+        Some((layer, object)) => format!(
+            "object {} {:?} at {},{} in {layer}",
+            object.id, object.name, object.x, object.y
+        ),
+        None => "deleted".into(),
+    }
+}
+
+fn grid(layer: &Layer<XmlFormat>) -> &[Vec<u32>] {
+    layer.data.as_ref().map_or(&[], |data| &data.data.0)
+}
+
+fn merge_cells(
+    path: &str,
+    base: &Layer<XmlFormat>,
+    ours: &mut Layer<XmlFormat>,
+    theirs: &Layer<XmlFormat>,
+    conflicts: &mut Vec<Conflict>,
+) {
+    let (b, t) = (grid(base), grid(theirs));
+    let size = |grid: &[Vec<u32>]| (grid.len(), grid.first().map_or(0, |row| row.len()));
+    if size(grid(ours)) != size(b) || size(t) != size(b) {
+        if grid(ours) == b {
+            ours.data = theirs.data.clone();
+            ours.width = theirs.width;
+            ours.height = theirs.height;
+        } else if t != b && t != grid(ours) {
+            let describe = |grid: &[Vec<u32>]| {
+                let (height, width) = size(grid);
+                format!("{width}x{height} cells")
+            };
+            conflicts.push(Conflict {
+                what: format!("layer {path} was resized"),
+                base: describe(b),
+                ours: describe(grid(ours)),
+                theirs: describe(t),
+            });
+        }
+        return;
+    }
+    let Some(data) = &mut ours.data else {
+        return;
+    };
+    for (y, row) in data.data.0.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let (b, t) = (b[y][x], t[y][x]);
+            if *cell == b {
+                *cell = t;
+            } else if t != b && t != *cell {
+                conflicts.push(Conflict {
+                    what: format!("layer {path} cell {x},{y}"),
+                    base: b.to_string(),
+                    ours: cell.to_string(),
+                    theirs: t.to_string(),
+                });
+            }
+        }
+    }
+}
+
+fn merge_layers(
+    base: &Map<XmlFormat>,
+    ours: &mut Map<XmlFormat>,
+    theirs: &Map<XmlFormat>,
+    conflicts: &mut Vec<Conflict>,
+) {
+    let base_layers = all_layers(&base.layers);
+    let their_layers = all_layers(&theirs.layers);
+
+    for (path, layer_type) in &their_layers {
+        let Some(theirs) = layer_type.layer() else {
+            continue;
+        };
+        match find(&base_layers, path) {
+            Some(base) => match layer_at(&mut ours.layers, path) {
+                Some(ours) => merge_cells(path, base, ours, theirs, conflicts),
+                None if base != theirs => conflicts.push(Conflict {
+                    what: format!("layer {path}"),
+                    base: "unchanged".into(),
+                    ours: "deleted".into(),
+                    theirs: "changed".into(),
+                }),
+                None => {}
+            },
+            None if layer_at(&mut ours.layers, path).is_none() => {
+                // Objects are added later together with the other new objects
+                let mut added = (*layer_type).clone();
+                let (mut next, _) = next_ids(ours);
+                for_each_layer(std::slice::from_mut(&mut added), &mut |layer| {
+                    layer.objects.clear();
+                    if let (Some(id), Some(next)) = (&mut layer.id, &mut next) {
+                        *id = *next;
+                        *next += 1;
+                    }
+                });
+                // Keep it above the layer it was added above in theirs
+                let name = |x: &LayerType<XmlFormat>| x.layer().map(|x| x.name.clone());
+                let below = their_layers
+                    .iter()
+                    .filter(|(other, _)| {
+                        other.rsplit_once('/').map(|x| x.0) == path.rsplit_once('/').map(|x| x.0)
+                    })
+                    .take_while(|(other, _)| other != path)
+                    .last()
+                    .and_then(|(_, x)| name(x));
+                if let Some(parent) = parent_at(&mut ours.layers, path) {
+                    let index = parent
+                        .iter()
+                        .position(|x| below.is_some() && name(x) == below)
+                        .map_or(0, |index| index + 1);
+                    parent.insert(index, added);
+                }
+            }
+            None => {}
+        }
+    }
+
+    for (path, layer_type) in &base_layers {
+        let Some(base_layer) = layer_type.layer() else {
+            continue;
+        };
+        if find(&their_layers, path).is_some() {
+            continue;
+        }
+        let Some(ours_layer) = layer_at(&mut ours.layers, path) else {
+            continue;
+        };
+        if ours_layer == base_layer {
+            let name = ours_layer.name.clone();
+            if let Some(parent) = parent_at(&mut ours.layers, path) {
+                parent.retain(|x| x.layer().is_none_or(|x| x.name != name));
+            }
+        } else {
+            conflicts.push(Conflict {
+                what: format!("layer {path}"),
+                base: "unchanged".into(),
+                ours: "changed".into(),
+                theirs: "deleted".into(),
+            });
+        }
+    }
+}
+
+fn objects(map: &Map<XmlFormat>) -> BTreeMap<u32, (String, &Object<XmlFormat>)> {
+    let mut res = BTreeMap::new();
+    for (path, layer_type) in all_layers(&map.layers) {
+        for object in layer_type.layer().into_iter().flat_map(|l| &l.objects) {
+            res.insert(object.id, (path.clone(), object));
+        }
+    }
+    res
+}
+
+fn merge_objects(
+    base: &Map<XmlFormat>,
+    ours: &mut Map<XmlFormat>,
+    theirs: &Map<XmlFormat>,
+    conflicts: &mut Vec<Conflict>,
+) {
+    let base_objects = objects(base);
+    let their_objects = objects(theirs);
+    let (_, mut nextobjectid) = next_ids(ours);
+    nextobjectid = nextobjectid.max(theirs.nextobjectid);
+
+    // Objects of theirs that replace the ones of ours with the same id, or
+    // remove them when there is no new version
+    let mut changes: BTreeMap<u32, Option<(String, Object<XmlFormat>)>> = BTreeMap::new();
+    let mut added = Vec::new();
+    {
+        let our_objects = objects(ours);
+        let ids = base_objects
+            .keys()
+            .chain(our_objects.keys())
+            .chain(their_objects.keys())
+            .copied()
+            .collect::<std::collections::BTreeSet<_>>();
+        for id in ids {
+            let (b, o, t) = (
+                base_objects.get(&id),
+                our_objects.get(&id),
+                their_objects.get(&id),
+            );
+            match (b, o, t) {
+                (None, Some(_), Some((path, object))) if o != t => {
+                    // Both sides created an object with the same id
+                    let mut object = (*object).clone();
+                    object.id = nextobjectid;
+                    nextobjectid += 1;
+                    added.push((path.clone(), object));
+                }
+                _ if o == b && t != b => {
+                    let new = t.map(|(path, object)| (path.clone(), (*object).clone()));
+                    changes.insert(id, new);
+                }
+                _ if o == b => {}
+                _ if t != b && t != o => conflicts.push(Conflict {
+                    what: format!("object {id}"),
+                    base: describe_object(b),
+                    ours: describe_object(o),
+                    theirs: describe_object(t),
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    walk(&mut ours.layers, "", &mut |path, layer| {
+        layer
+            .objects
+            .retain_mut(|object| match changes.get_mut(&object.id) {
+                None => true,
+                Some(change) => match change.take_if(|(other, _)| other == path) {
+                    Some((_, new)) => {
+                        *object = new;
+                        true
+                    }
+                    None => false,
+                },
+            });
+    });
+    let mut pending = changes.into_values().flatten().collect::<Vec<_>>();
+    pending.append(&mut added);
+    for (path, object) in pending {
+        match layer_at(&mut ours.layers, &path) {
+            Some(layer) => layer.objects.push(object),
+            None => conflicts.push(Conflict {
+                what: format!("object {}", object.id),
+                base: "-".into(),
+                ours: format!("layer {path} deleted"),
+                theirs: describe_object(Some(&(path.clone(), &object))),
+            }),
+        }
+    }
+    ours.nextobjectid = nextobjectid;
+}
+
+fn walk(layers: &mut Layers, prefix: &str, f: &mut impl FnMut(&str, &mut Layer<XmlFormat>)) {
+    for layer in layers.iter_mut().filter_map(|x| x.layer_mut()) {
+        let path = format!("{prefix}{}", layer.name);
+        f(&path, layer);
+        walk(&mut layer.layers, &format!("{path}/"), f);
+    }
+}
+
+/// Applies the changes from `base` to `theirs` to `ours` where they don't
+/// collide with changes from `base` to `ours`, returning the collisions
+pub fn merge(
+    base: &Map<XmlFormat>,
+    ours: &mut Map<XmlFormat>,
+    theirs: &Map<XmlFormat>,
+) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    if ours.tilesets != theirs.tilesets {
+        if ours.tilesets == base.tilesets {
+            ours.tilesets = theirs.tilesets.clone();
+        } else if theirs.tilesets != base.tilesets {
+            let names = |map: &Map<XmlFormat>| {
+                map.tilesets
+                    .iter()
+                    .map(|t| format!("{} at {}", t.name, t.firstgid))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            conflicts.push(Conflict {
+                what: "tilesets".into(),
+                base: names(base),
+                ours: names(ours),
+                theirs: names(theirs),
+            });
+        }
+    }
+    if (ours.width, ours.height) == (base.width, base.height) {
+        (ours.width, ours.height) = (theirs.width, theirs.height);
+    }
+    merge_layers(base, ours, theirs, &mut conflicts);
+    merge_objects(base, ours, theirs, &mut conflicts);
+    let (nextlayerid, _) = next_ids(ours);
+    ours.nextlayerid = nextlayerid.max(theirs.nextlayerid);
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::de::from_str;
+
+    /// 2x2 map with the given layers, `cells` fills a tile layer called Ground
+    fn map(cells: &str, objects: &str, nextobjectid: u32) -> Map<XmlFormat> {
+        from_str(&format!(
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="2" height="2" tilewidth="16" tileheight="16" infinite="0"
+                nextlayerid="4" nextobjectid="{nextobjectid}">
+                <layer id="1" name="Ground" width="2" height="2">
+                    <data encoding="csv">{cells}</data>
+                </layer>
+                {objects}
+            </map>"#
+        ))
+        .unwrap()
+    }
+
+    fn cells(map: &Map<XmlFormat>) -> Vec<Vec<u32>> {
+        let layer = all_layers(&map.layers)[0].1.layer().unwrap().clone();
+        layer.data.unwrap().data.0
+    }
+
+    fn layer_objects(map: &Map<XmlFormat>) -> Vec<(String, u32, f64)> {
+        all_layers(&map.layers)
+            .iter()
+            .flat_map(|(path, layer)| {
+                let objects = layer.layer().into_iter().flat_map(|l| &l.objects);
+                objects.map(move |object| (path.clone(), object.id, object.x))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_cells_changed_on_either_side() {
+        let base = map("0,0,\n0,0", "", 1);
+        let mut ours = map("1,0,\n0,0", "", 1);
+        let theirs = map("0,0,\n0,2", "", 1);
+        assert!(merge(&base, &mut ours, &theirs).is_empty());
+        assert_eq!(cells(&ours), vec![vec![1, 0], vec![0, 2]]);
+    }
+
+    #[test]
+    fn reports_cells_changed_on_both_sides() {
+        let base = map("0,0,\n0,0", "", 1);
+        let mut ours = map("1,0,\n0,0", "", 1);
+        let theirs = map("2,0,\n0,0", "", 1);
+        let conflicts = merge(&base, &mut ours, &theirs);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].what, "layer Ground cell 0,0");
+        assert_eq!(cells(&ours), vec![vec![1, 0], vec![0, 0]]);
+    }
+
+    #[test]
+    fn renumbers_objects_added_with_the_same_id() {
+        let layer = |x| {
+            format!(r#"<objectgroup id="2" name="Things"><object id="1" x="{x}"/></objectgroup>"#)
+        };
+        let base = map("0,0,\n0,0", r#"<objectgroup id="2" name="Things"/>"#, 1);
+        let mut ours = map("0,0,\n0,0", &layer(8), 2);
+        let theirs = map("0,0,\n0,0", &layer(16), 2);
+        assert!(merge(&base, &mut ours, &theirs).is_empty());
+        assert_eq!(
+            layer_objects(&ours),
+            vec![("Things".into(), 1, 8.0), ("Things".into(), 2, 16.0)]
+        );
+        assert_eq!(ours.nextobjectid, 3);
+    }
+
+    #[test]
+    fn moves_objects_to_their_new_layer() {
+        let layers = |a, b| {
+            format!(
+                r#"<objectgroup id="2" name="A">{a}</objectgroup>
+                <objectgroup id="3" name="B">{b}</objectgroup>"#
+            )
+        };
+        let object = r#"<object id="1" x="8"/>"#;
+        let base = map("0,0,\n0,0", &layers(object, ""), 2);
+        let mut ours = map("1,0,\n0,0", &layers(object, ""), 2);
+        let theirs = map("0,0,\n0,0", &layers("", object), 2);
+        assert!(merge(&base, &mut ours, &theirs).is_empty());
+        assert_eq!(layer_objects(&ours), vec![("B".into(), 1, 8.0)]);
+    }
+
+    #[test]
+    fn reports_layers_deleted_on_one_side_and_changed_on_the_other() {
+        let things = r#"<objectgroup id="2" name="Things"><object id="1" x="8"/></objectgroup>"#;
+        let moved = r#"<objectgroup id="2" name="Things"><object id="1" x="24"/></objectgroup>"#;
+        let base = map("0,0,\n0,0", things, 2);
+        let mut ours = map("0,0,\n0,0", moved, 2);
+        let theirs = map("0,0,\n0,0", "", 2);
+        let conflicts = merge(&base, &mut ours, &theirs);
+        assert!(conflicts
+            .iter()
+            .any(|c| c.what == "layer Things" && c.ours == "changed" && c.theirs == "deleted"));
+
+        let mut ours = map("0,0,\n0,0", "", 2);
+        let conflicts = merge(&base, &mut ours, &map("0,0,\n0,0", moved, 2));
+        assert!(conflicts
+            .iter()
+            .any(|c| c.what == "layer Things" && c.ours == "deleted" && c.theirs == "changed"));
+    }
+
+    #[test]
+    fn finds_content_the_model_drops() {
+        let plain = to_xml(&map("0,0,\n0,0", "", 1));
+        assert_eq!(lossless(&plain, &from_str(&plain).unwrap()), Ok(()));
+
+        let opacity = plain.replace(r#"name="Ground""#, r#"name="Ground" opacity="0.5""#);
+        assert!(lossless(&opacity, &from_str(&opacity).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::canvas::resize_map;
+use crate::stitch::rebase;
+use crate::tileset::used_firstgids;
+use crate::{for_each_layer, write_xml, JsonFormat, Map, MapFormat, Offset, XmlFormat};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorldMap {
+    file_name: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct World {
+    maps: Vec<WorldMap>,
+    only_show_adjacent_maps: bool,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Part of the map starting at tile `x`,`y`, with only the objects placed
+/// inside of it and the tilesets it uses
+fn region(map: &Map<XmlFormat>, x: u32, y: u32, width: u32, height: u32) -> Map<XmlFormat> {
+    let (tilewidth, tileheight) = (map.tilewidth as f64, map.tileheight as f64);
+    let (left, top) = (x as f64 * tilewidth, y as f64 * tileheight);
+    let (right, bottom) = (
+        left + width as f64 * tilewidth,
+        top + height as f64 * tileheight,
+    );
+    let mut res = map.clone();
+    for_each_layer(&mut res.layers, &mut |layer| {
+        layer.objects.retain(|object| {
+            object.x >= left && object.x < right && object.y >= top && object.y < bottom
+        });
+    });
+    let offset = Offset {
+        x: -(x as i32),
+        y: -(y as i32),
+    };
+    resize_map(&mut res, width, height, offset, false);
+
+    let used = used_firstgids(&mut res);
+    res.tilesets
+        .retain(|tileset| used.contains(&tileset.firstgid));
+    res
+}
+
+/// Cuts the map into regions of `width`x`height` tiles saved next to each other
+/// in `output`, together with a .world file placing them
+pub fn split(
+    map: &Map<XmlFormat>,
+    path: &Path,
+    width: u32,
+    height: u32,
+    output: &Path,
+    format: MapFormat,
+) {
+    assert!(width > 0 && height > 0, "Region size can't be zero");
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let stem = path
+        .file_stem()
+        .expect("Map path should have a file name")
+        .to_string_lossy();
+    fs::create_dir_all(output)
+        .unwrap_or_else(|err| panic!("Can't create {}: {err}", output.display()));
+
+    let mut world = World {
+        maps: Vec::new(),
+        only_show_adjacent_maps: false,
+        kind: "world".into(),
+    };
+    for y in (0..map.height).step_by(height as usize) {
+        for x in (0..map.width).step_by(width as usize) {
+            let (region_width, region_height) =
+                (width.min(map.width - x), height.min(map.height - y));
+            let mut res = region(map, x, y, region_width, region_height);
+            for tileset in &mut res.tilesets {
+                if let Some(source) = &mut tileset.source {
+                    *source = rebase(source, dir, output);
+                } else if let Some(image) = &mut tileset.image {
+                    image.source = rebase(&image.source, dir, output);
+                }
+            }
+
+            let extension = match format {
+                MapFormat::Tmx => "tmx",
+                MapFormat::Json => "json",
+            };
+            let file_name = format!("{stem}_{}_{}.{extension}", x / width, y / height);
+            let file = output.join(&file_name);
+            match format {
+                MapFormat::Tmx => write_xml(&file, &res),
+                MapFormat::Json => {
+                    let res: Map<JsonFormat> = res.into();
+                    fs::write(&file, serde_json::to_string_pretty(&res).unwrap())
+                        .unwrap_or_else(|err| panic!("Can't write {}: {err}", file.display()));
+                }
+            }
+            world.maps.push(WorldMap {
+                file_name,
+                x: x * map.tilewidth,
+                y: y * map.tileheight,
+                width: region_width * map.tilewidth,
+                height: region_height * map.tileheight,
+            });
+        }
+    }
+
+    let file = output.join(format!("{stem}.world"));
+    fs::write(&file, serde_json::to_string_pretty(&world).unwrap())
+        .unwrap_or_else(|err| panic!("Can't write {}: {err}", file.display()));
+}
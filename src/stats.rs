@@ -0,0 +1,260 @@
+use crate::{
+    all_layers, tileset_for_gid, Map, OutputFormat, Region, SerializationFormat, GID_MASK,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Default, Serialize)]
+struct Usage {
+    cells: u32,
+    used: u32,
+    empty_ratio: f64,
+    bounds: Option<Region>,
+}
+
+impl Usage {
+    fn add(&mut self, x: u32, y: u32, used: bool) {
+        self.cells += 1;
+        if used {
+            self.used += 1;
+            self.include(&Region {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            });
+        }
+    }
+
+    fn include(&mut self, other: &Region) {
+        self.bounds = Some(match self.bounds.take() {
+            None => other.clone(),
+            Some(b) => {
+                let (x0, y0) = (b.x.min(other.x), b.y.min(other.y));
+                let x1 = (b.x + b.width).max(other.x + other.width);
+                let y1 = (b.y + b.height).max(other.y + other.height);
+                Region {
+                    x: x0,
+                    y: y0,
+                    width: x1 - x0,
+                    height: y1 - y0,
+                }
+            }
+        });
+    }
+
+    fn merge(&mut self, other: &Usage) {
+        self.cells += other.cells;
+        self.used += other.used;
+        if let Some(b) = &other.bounds {
+            self.include(b);
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.cells > 0 {
+            self.empty_ratio = (self.cells - self.used) as f64 / self.cells as f64;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LayerStats {
+    name: String,
+    #[serde(flatten)]
+    usage: Usage,
+    tilesets: BTreeMap<String, u32>,
+}
+
+#[derive(Serialize)]
+struct TileSetStats {
+    name: String,
+    firstgid: u32,
+    used: u32,
+    distinct: u32,
+}
+
+#[derive(Serialize)]
+struct TileStats {
+    gid: u32,
+    tileset: Option<String>,
+    id: Option<u32>,
+    count: u32,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    #[serde(flatten)]
+    usage: Usage,
+    layers: Vec<LayerStats>,
+    tilesets: Vec<TileSetStats>,
+    tiles: Vec<TileStats>,
+}
+
+fn collect_layers<T>(map: &Map<T>, histogram: &mut BTreeMap<u32, u32>) -> Vec<LayerStats>
+where
+    T: SerializationFormat,
+{
+    let mut res = Vec::new();
+    for (name, layer_type) in all_layers(&map.layers) {
+        let Some(data) = layer_type.layer().and_then(|layer| layer.data.as_ref()) else {
+            continue;
+        };
+        let mut usage = Usage::default();
+        let mut tilesets = BTreeMap::new();
+        for (y, row) in data.data.0.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let gid = cell & GID_MASK;
+                usage.add(x as u32, y as u32, gid != 0);
+                if gid == 0 {
+                    continue;
+                }
+                *histogram.entry(gid).or_default() += 1;
+                let tileset = tileset_for_gid(&map.tilesets, gid).map_or("?", |t| &t.name);
+                *tilesets.entry(tileset.to_string()).or_default() += 1;
+            }
+        }
+        usage.finish();
+        res.push(LayerStats {
+            name,
+            usage,
+            tilesets,
+        });
+    }
+    res
+}
+
+fn percent(ratio: f64) -> String {
+    format!("{:.1}%", ratio * 100.0)
+}
+
+fn bounds(bounds: &Option<Region>) -> String {
+    match bounds {
+        Some(b) => format!("{},{} {}x{}", b.x, b.y, b.width, b.height),
+        None => "-".into(),
+    }
+}
+
+fn print_table(stats: &Stats) {
+    println!(
+        "Cells: {}, used: {}, empty: {}, bounds: {}",
+        stats.usage.cells,
+        stats.usage.used,
+        percent(stats.usage.empty_ratio),
+        bounds(&stats.usage.bounds)
+    );
+
+    let width = stats
+        .layers
+        .iter()
+        .map(|l| l.name.len())
+        .chain(stats.tilesets.iter().map(|t| t.name.len()))
+        .chain([7])
+        .max()
+        .unwrap_or_default();
+
+    println!();
+    println!(
+        "{:<width$}  {:>6}  {:>6}  {:>6}  {:<12}  Tilesets",
+        "Layer", "Cells", "Used", "Empty", "Bounds"
+    );
+    for layer in &stats.layers {
+        let tilesets = layer
+            .tilesets
+            .iter()
+            .map(|(name, count)| format!("{name}: {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{:<width$}  {:>6}  {:>6}  {:>6}  {:<12}  {}",
+            layer.name,
+            layer.usage.cells,
+            layer.usage.used,
+            percent(layer.usage.empty_ratio),
+            bounds(&layer.usage.bounds),
+            tilesets
+        );
+    }
+
+    println!();
+    println!(
+        "{:<width$}  {:>8}  {:>6}  {:>8}",
+        "Tileset", "Firstgid", "Used", "Distinct"
+    );
+    for tileset in &stats.tilesets {
+        println!(
+            "{:<width$}  {:>8}  {:>6}  {:>8}",
+            tileset.name, tileset.firstgid, tileset.used, tileset.distinct
+        );
+    }
+
+    println!();
+    println!(
+        "{:>8}  {:<width$}  {:>6}  {:>6}",
+        "Gid", "Tileset", "Id", "Count"
+    );
+    for tile in &stats.tiles {
+        println!(
+            "{:>8}  {:<width$}  {:>6}  {:>6}",
+            tile.gid,
+            tile.tileset.as_deref().unwrap_or("?"),
+            tile.id.map_or("?".into(), |id| id.to_string()),
+            tile.count
+        );
+    }
+}
+
+pub fn stats<T>(map: &Map<T>, format: OutputFormat)
+where
+    T: SerializationFormat,
+{
+    let mut histogram = BTreeMap::new();
+    let layers = collect_layers(map, &mut histogram);
+
+    let mut usage = Usage::default();
+    for layer in &layers {
+        usage.merge(&layer.usage);
+    }
+    usage.finish();
+
+    let tilesets = map
+        .tilesets
+        .iter()
+        .map(|tileset| {
+            let tiles = histogram.iter().filter(|(gid, _)| {
+                tileset_for_gid(&map.tilesets, **gid).map(|t| t.firstgid) == Some(tileset.firstgid)
+            });
+            TileSetStats {
+                name: tileset.name.clone(),
+                firstgid: tileset.firstgid,
+                used: tiles.clone().map(|(_, count)| count).sum(),
+                distinct: tiles.count() as u32,
+            }
+        })
+        .collect();
+
+    let mut tiles = histogram
+        .iter()
+        .map(|(&gid, &count)| {
+            let tileset = tileset_for_gid(&map.tilesets, gid);
+            TileStats {
+                gid,
+                tileset: tileset.map(|t| t.name.clone()),
+                id: tileset.map(|t| gid - t.firstgid),
+                count,
+            }
+        })
+        .collect::<Vec<_>>();
+    tiles.sort_by(|a, b| b.count.cmp(&a.count).then(a.gid.cmp(&b.gid)));
+
+    let stats = Stats {
+        usage,
+        layers,
+        tilesets,
+        tiles,
+    };
+    match format {
+        OutputFormat::Table => print_table(&stats),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats).unwrap()),
+    }
+}
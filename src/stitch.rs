@@ -0,0 +1,241 @@
+use crate::canvas::resize_map;
+use crate::{next_ids, remap_gids, LayerType, Map, Offset, Placement, TileSet, XmlFormat};
+use std::collections::HashMap;
+use std::fs;
+use std::mem::discriminant;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Path of `path`, relative to `from`, as seen from `to`
+pub fn rebase(path: &str, from: &Path, to: &Path) -> String {
+    let joined = from.join(path);
+    let (Ok(full), Ok(to)) = (fs::canonicalize(&joined), fs::canonicalize(to)) else {
+        return joined.to_string_lossy().into_owned();
+    };
+    let common = full
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut res = PathBuf::new();
+    for _ in to.components().skip(common) {
+        res.push("..");
+    }
+    res.extend(full.components().skip(common));
+    res.to_string_lossy().into_owned()
+}
+
+/// Identifies a tileset across maps: the .tsx file, or the name and image of
+/// embedded tilesets
+fn tileset_key(tileset: &TileSet<XmlFormat>, dir: &Path) -> String {
+    let full = |path: &str| {
+        let joined = dir.join(path);
+        fs::canonicalize(&joined)
+            .unwrap_or(joined)
+            .to_string_lossy()
+            .into_owned()
+    };
+    if let Some(source) = &tileset.source {
+        return full(source);
+    }
+    let image = tileset
+        .image
+        .as_ref()
+        .map_or(String::new(), |image| full(&image.source));
+    format!("{}|{image}", tileset.name)
+}
+
+/// Hands out layer and object ids that aren't used in the output map yet
+struct Ids {
+    layer: u32,
+    object: u32,
+}
+
+impl Ids {
+    fn new(map: &mut Map<XmlFormat>) -> Self {
+        let (layer, object) = next_ids(map);
+        Ids {
+            layer: layer.unwrap_or(1),
+            object,
+        }
+    }
+
+    fn store(&self, map: &mut Map<XmlFormat>) {
+        if map.nextlayerid.is_some() {
+            map.nextlayerid = Some(self.layer);
+        }
+        map.nextobjectid = self.object;
+    }
+
+    fn renumber(&mut self, layer_type: &mut LayerType<XmlFormat>) {
+        let Some(layer) = layer_type.layer_mut() else {
+            return;
+        };
+        if layer.id.is_some() {
+            layer.id = Some(self.layer);
+            self.layer += 1;
+        }
+        for object in &mut layer.objects {
+            object.id = self.object;
+            self.object += 1;
+        }
+        for inner in &mut layer.layers {
+            self.renumber(inner);
+        }
+    }
+}
+
+/// Cells where empty cells of the added map clear the target as well
+struct Area {
+    x: Range<usize>,
+    y: Range<usize>,
+}
+
+/// Adds the layers of `from` to the layers with the same name and type in
+/// `into`, drawing them over the existing tiles
+fn merge_layers(
+    into: &mut Vec<LayerType<XmlFormat>>,
+    from: Vec<LayerType<XmlFormat>>,
+    clear: Option<&Area>,
+    ids: &mut Ids,
+) {
+    for mut layer_type in from {
+        let Some(name) = layer_type.layer().map(|layer| layer.name.clone()) else {
+            continue;
+        };
+        let existing = into.iter_mut().find(|other| {
+            discriminant(*other) == discriminant(&layer_type)
+                && other.layer().is_some_and(|other| other.name == name)
+        });
+        let Some(existing) = existing else {
+            ids.renumber(&mut layer_type);
+            into.push(layer_type);
+            continue;
+        };
+        let (Some(target), Some(layer)) = (existing.layer_mut(), layer_type.layer_mut()) else {
+            continue;
+        };
+        if let (Some(target), Some(data)) = (&mut target.data, &layer.data) {
+            for (y, (target, row)) in target.data.0.iter_mut().zip(&data.data.0).enumerate() {
+                for (x, (target, &gid)) in target.iter_mut().zip(row).enumerate() {
+                    if gid != 0 || clear.is_some_and(|a| a.x.contains(&x) && a.y.contains(&y)) {
+                        *target = gid;
+                    }
+                }
+            }
+        }
+        for mut object in layer.objects.drain(..) {
+            object.id = ids.object;
+            ids.object += 1;
+            target.objects.push(object);
+        }
+        let inner = std::mem::take(&mut layer.layers);
+        merge_layers(&mut target.layers, inner, clear, ids);
+    }
+}
+
+/// Draws `other`, read from `path`, over `map` with its top left corner at
+/// `offset`, adding the tilesets `map` doesn't have yet
+fn place(
+    map: &mut Map<XmlFormat>,
+    dir: &Path,
+    path: &Path,
+    mut other: Map<XmlFormat>,
+    offset: Offset,
+    clear: bool,
+    ids: &mut Ids,
+) {
+    assert!(
+        other.tilewidth == map.tilewidth && other.tileheight == map.tileheight,
+        "{} has a different tile size",
+        path.display()
+    );
+    let mut keys = map
+        .tilesets
+        .iter()
+        .map(|tileset| (tileset_key(tileset, dir), tileset.firstgid))
+        .collect::<HashMap<_, _>>();
+    let other_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut firstgids = Vec::new();
+    for mut tileset in std::mem::take(&mut other.tilesets) {
+        let key = tileset_key(&tileset, &other_dir);
+        let old = tileset.firstgid;
+        let new = *keys.entry(key).or_insert_with(|| {
+            let firstgid = map
+                .tilesets
+                .iter()
+                .map(|t| t.firstgid + t.tilecount)
+                .max()
+                .unwrap_or(1);
+            tileset.firstgid = firstgid;
+            if let Some(source) = &mut tileset.source {
+                *source = rebase(source, &other_dir, dir);
+            } else if let Some(image) = &mut tileset.image {
+                image.source = rebase(&image.source, &other_dir, dir);
+            }
+            map.tilesets.push(tileset);
+            firstgid
+        });
+        firstgids.push((old, new));
+    }
+    remap_gids(&mut other, |gid| {
+        firstgids
+            .iter()
+            .filter(|(old, _)| *old <= gid)
+            .max_by_key(|(old, _)| *old)
+            .map_or(gid, |(old, new)| gid - old + new)
+    });
+
+    let clamp = |start: i32, len: u32, limit: u32| {
+        start.clamp(0, limit as i32) as usize..(start + len as i32).clamp(0, limit as i32) as usize
+    };
+    let area = Area {
+        x: clamp(offset.x, other.width, map.width),
+        y: clamp(offset.y, other.height, map.height),
+    };
+    resize_map(&mut other, map.width, map.height, offset, true);
+    merge_layers(&mut map.layers, other.layers, clear.then_some(&area), ids);
+}
+
+/// Places `maps` at their offsets next to `map`, which stays at 0,0
+pub fn stitch(map: &mut Map<XmlFormat>, dir: &Path, maps: Vec<(Placement, Map<XmlFormat>)>) {
+    let origin = Offset { x: 0, y: 0 };
+    let placements = maps
+        .iter()
+        .map(|(placement, other)| (placement.offset, other.width, other.height))
+        .chain([(origin, map.width, map.height)])
+        .collect::<Vec<_>>();
+    let left = placements.iter().map(|(o, _, _)| o.x).min().unwrap_or(0);
+    let top = placements.iter().map(|(o, _, _)| o.y).min().unwrap_or(0);
+    let right = placements.iter().map(|(o, w, _)| o.x + *w as i32).max();
+    let bottom = placements.iter().map(|(o, _, h)| o.y + *h as i32).max();
+    let width = (right.unwrap_or(0) - left) as u32;
+    let height = (bottom.unwrap_or(0) - top) as u32;
+    let shift = |offset: Offset| Offset {
+        x: offset.x - left,
+        y: offset.y - top,
+    };
+    resize_map(map, width, height, shift(origin), false);
+
+    let mut ids = Ids::new(map);
+    for (placement, other) in maps {
+        let offset = shift(placement.offset);
+        place(map, dir, &placement.path, other, offset, false, &mut ids);
+    }
+    ids.store(map);
+}
+
+/// Pastes the layers of `stamp` into the layers with the same names in `map`.
+/// Empty cells of the stamp clear the map unless `skip_empty` is set.
+pub fn paste(
+    map: &mut Map<XmlFormat>,
+    dir: &Path,
+    path: &Path,
+    stamp: Map<XmlFormat>,
+    offset: Offset,
+    skip_empty: bool,
+) {
+    let mut ids = Ids::new(map);
+    place(map, dir, path, stamp, offset, !skip_empty, &mut ids);
+    ids.store(map);
+}
@@ -0,0 +1,182 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+struct Element {
+    name: String,
+    attributes: BTreeMap<String, String>,
+    /// Gids of `<tile>` children for data without encoding
+    tiles: Vec<u32>,
+}
+
+fn element(start: &BytesStart) -> Element {
+    let attributes = start
+        .attributes()
+        .map(|attribute| {
+            let attribute = attribute.expect("Should have been a valid attribute");
+            let value = attribute
+                .unescape_value()
+                .expect("Should have been a valid attribute value");
+            (
+                String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+                value.into_owned(),
+            )
+        })
+        .collect();
+    Element {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        attributes,
+        tiles: Vec::new(),
+    }
+}
+
+fn print_element(out: &mut String, element: &Element, depth: usize, keep_encoding: bool) {
+    let _ = write!(out, "{}{}", " ".repeat(depth), element.name);
+    for (key, value) in &element.attributes {
+        // The cells are printed decoded, so the encoding doesn't matter
+        if !keep_encoding && element.name == "data" && (key == "encoding" || key == "compression") {
+            continue;
+        }
+        let _ = write!(out, " {key}={value:?}");
+    }
+    out.push('\n');
+}
+
+fn decompress(bytes: Vec<u8>, compression: Option<&str>) -> Result<Vec<u8>, String> {
+    let mut res = Vec::new();
+    let read = match compression {
+        None => return Ok(bytes),
+        Some("zlib") => ZlibDecoder::new(&bytes[..]).read_to_end(&mut res),
+        Some("gzip") => GzDecoder::new(&bytes[..]).read_to_end(&mut res),
+        Some("zstd") => ruzstd::StreamingDecoder::new(&bytes[..])
+            .map_err(|err| format!("Invalid zstd data: {err}"))?
+            .read_to_end(&mut res),
+        Some(other) => return Err(format!("Unknown compression {other}")),
+    };
+    read.map_err(|err| format!("Can't decompress data: {err}"))?;
+    Ok(res)
+}
+
+/// Gids of the text of a `<data>` or `<chunk>` element
+pub fn decode(
+    text: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> Result<Vec<u32>, String> {
+    match encoding {
+        Some("csv") => text
+            .split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| format!("Invalid gid {gid:?}")))
+            .collect(),
+        Some("base64") => {
+            let bytes = STANDARD
+                .decode(text.trim())
+                .map_err(|err| format!("Invalid base64 data: {err}"))?;
+            Ok(decompress(bytes, compression)?
+                .chunks_exact(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect())
+        }
+        Some(other) => Err(format!("Unknown encoding {other}")),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn print_rows(out: &mut String, gids: &[u32], width: usize, depth: usize) {
+    for row in gids.chunks(width.max(1)) {
+        let row = row.iter().map(u32::to_string).collect::<Vec<_>>();
+        let _ = writeln!(out, "{}{}", " ".repeat(depth), row.join(","));
+    }
+}
+
+/// Width of the rows of the innermost `<data>` or `<chunk>` in `stack`
+fn row_width(stack: &[Element]) -> usize {
+    stack
+        .iter()
+        .rev()
+        .filter(|element| element.name != "data")
+        .find_map(|element| element.attributes.get("width"))
+        .and_then(|width| width.parse().ok())
+        .unwrap_or(1)
+}
+
+/// The .tmx or .tsx file one element per line with sorted attributes and tile
+/// data decoded into one line per row. The data encoding is left out unless
+/// `keep_encoding` is set.
+pub fn normalize(contents: &str, keep_encoding: bool) -> Result<String, String> {
+    let mut out = String::new();
+    let mut reader = Reader::from_str(contents);
+    reader.trim_text(true);
+    let mut stack: Vec<Element> = Vec::new();
+    loop {
+        let event = reader.read_event().map_err(|err| err.to_string())?;
+        match event {
+            Event::Start(start) => {
+                let element = element(&start);
+                print_element(&mut out, &element, stack.len(), keep_encoding);
+                stack.push(element);
+            }
+            Event::Empty(start) => {
+                let element = element(&start);
+                match stack.last_mut() {
+                    Some(data) if data.name == "data" && element.name == "tile" => {
+                        let gid = element
+                            .attributes
+                            .get("gid")
+                            .map_or(Ok(0), |gid| gid.parse())
+                            .map_err(|err| format!("Invalid gid: {err}"))?;
+                        data.tiles.push(gid);
+                    }
+                    _ => print_element(&mut out, &element, stack.len(), keep_encoding),
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or("Unmatched end tag")?;
+                if !element.tiles.is_empty() {
+                    print_rows(&mut out, &element.tiles, row_width(&stack), stack.len() + 1);
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|err| err.to_string())?;
+                let depth = stack.len();
+                let data = stack.iter().rev().find(|element| element.name == "data");
+                match (stack.last(), data) {
+                    (Some(last), Some(data)) if last.name == "data" || last.name == "chunk" => {
+                        let attribute = |key| data.attributes.get(key).map(String::as_str);
+                        let gids = decode(&text, attribute("encoding"), attribute("compression"))?;
+                        print_rows(&mut out, &gids, row_width(&stack), depth);
+                    }
+                    _ => {
+                        for line in text.lines() {
+                            let _ = writeln!(out, "{}{line}", " ".repeat(depth));
+                        }
+                    }
+                }
+            }
+            Event::CData(text) => {
+                for line in String::from_utf8_lossy(&text).lines() {
+                    let _ = writeln!(out, "{}{line}", " ".repeat(stack.len()));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Prints the normalized file for `diff.tmx.textconv`
+pub fn textconv(path: &Path) {
+    let contents = fs::read_to_string(path).expect("Should have been able to read the file");
+    let res = normalize(&contents, false).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    print!("{res}");
+}
@@ -0,0 +1,402 @@
+use crate::atlas::{copy_tiles, image_size, Atlas};
+use crate::{
+    for_each_cell, for_each_object, remap_gids, tileset_for_gid, LayerType, Map,
+    SerializationFormat, TileSet, GID_MASK,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+fn tileset_index<T>(map: &Map<T>, name: &str) -> usize
+where
+    T: SerializationFormat,
+{
+    map.tilesets
+        .iter()
+        .position(|tileset| tileset.name == name)
+        .unwrap_or_else(|| panic!("No tileset named {name}"))
+}
+
+fn embedded_tileset_index<T>(map: &Map<T>, name: &str) -> usize
+where
+    T: SerializationFormat,
+{
+    let index = tileset_index(map, name);
+    assert!(
+        map.tilesets[index].source.is_none(),
+        "Tileset {name} is stored in a .tsx file, embed it first"
+    );
+    index
+}
+
+/// Index of the tileset containing `gid` (without flags) given the firstgid of every tileset
+pub fn index_for_gid(firstgids: &[u32], gid: u32) -> Option<usize> {
+    firstgids
+        .iter()
+        .enumerate()
+        .filter(|(_, &firstgid)| firstgid <= gid)
+        .max_by_key(|(_, &firstgid)| firstgid)
+        .map(|(i, _)| i)
+}
+
+/// Firstgids of the tilesets that have tiles in layers or tile objects
+pub fn used_firstgids<T>(map: &mut Map<T>) -> HashSet<u32>
+where
+    T: SerializationFormat,
+{
+    let mut used = HashSet::new();
+    for_each_cell(&mut map.layers, &mut |cell| {
+        used.insert(*cell & GID_MASK);
+    });
+    for_each_object(&mut map.layers, &mut |object| {
+        if let Some(gid) = object.gid {
+            used.insert(gid & GID_MASK);
+        }
+    });
+    used.into_iter()
+        .filter(|&gid| gid != 0)
+        .filter_map(|gid| tileset_for_gid(&map.tilesets, gid).map(|t| t.firstgid))
+        .collect()
+}
+
+/// Drops tilesets without used tiles and moves the others right after each other
+pub fn compact<T>(map: &mut Map<T>)
+where
+    T: SerializationFormat,
+{
+    let used = used_firstgids(map);
+    map.tilesets
+        .retain(|tileset| used.contains(&tileset.firstgid));
+    let firstgids = map.tilesets.iter().map(|t| t.firstgid).collect::<Vec<_>>();
+    renumber_firstgids(&mut map.tilesets);
+    let new_firstgids = map.tilesets.iter().map(|t| t.firstgid).collect::<Vec<_>>();
+    remap_gids(map, |gid| match index_for_gid(&firstgids, gid) {
+        Some(i) => gid - firstgids[i] + new_firstgids[i],
+        None => gid,
+    });
+}
+
+/// Places tilesets right after each other starting at 1
+fn renumber_firstgids<T>(tilesets: &mut [TileSet<T>])
+where
+    T: SerializationFormat,
+{
+    let mut firstgid = 1;
+    for tileset in tilesets {
+        tileset.firstgid = firstgid;
+        firstgid += tileset.tilecount;
+    }
+}
+
+/// Changes local tile ids in the per-tile data, animations and wang sets of a tileset
+fn remap_tile_data<T>(tileset: &mut TileSet<T>, f: impl Fn(u32) -> u32)
+where
+    T: SerializationFormat,
+{
+    for tile in &mut tileset.tiles {
+        tile.id = f(tile.id);
+        if let Some(animation) = &mut tile.animation {
+            for frame in &mut animation.frames {
+                frame.tileid = f(frame.tileid);
+            }
+        }
+    }
+    let remap_tile = |tile: &mut i32| {
+        if *tile >= 0 {
+            *tile = f(*tile as u32) as i32;
+        }
+    };
+    for wangset in tileset.wangsets.iter_mut().flat_map(|w| &mut w.wangsets) {
+        remap_tile(&mut wangset.tile);
+        for color in &mut wangset.colors {
+            remap_tile(&mut color.tile);
+        }
+        for wangtile in &mut wangset.wangtiles {
+            wangtile.tileid = f(wangtile.tileid);
+        }
+    }
+}
+
+/// Stacks the image of `from` below the one of `into`, so tiles keep their
+/// place in the grid and only get new ids if the number of columns grows.
+/// The combined image is saved to `output`, relative to `dir`.
+pub fn merge<T>(map: &mut Map<T>, dir: &Path, into: &str, from: &str, output: Option<&Path>)
+where
+    T: SerializationFormat,
+{
+    let a = embedded_tileset_index(map, into);
+    let b = embedded_tileset_index(map, from);
+    assert!(a != b, "Can't merge a tileset with itself");
+    let (ta, tb) = (&map.tilesets[a], &map.tilesets[b]);
+    assert!(
+        ta.tilewidth == tb.tilewidth && ta.tileheight == tb.tileheight,
+        "Tilesets have different tile sizes"
+    );
+    assert!(
+        ta.margin == tb.margin && ta.spacing == tb.spacing,
+        "Tilesets have different margin or spacing"
+    );
+
+    let (columns_a, columns_b) = (ta.columns.max(1), tb.columns.max(1));
+    let rows_a = ta.tilecount.div_ceil(columns_a);
+    let rows_b = tb.tilecount.div_ceil(columns_b);
+    let columns = columns_a.max(columns_b);
+    let map_a = move |id: u32| id / columns_a * columns + id % columns_a;
+    let map_b = move |id: u32| (rows_a + id / columns_b) * columns + id % columns_b;
+
+    let (width, height) = image_size(ta, columns, rows_a + rows_b);
+    let image = match (&ta.image, &tb.image) {
+        (Some(_), Some(_)) => {
+            let output =
+                output.expect("Both tilesets have images, pass --image to save the merged one");
+            let mut dst = Atlas::new(width, height);
+            copy_tiles(&mut dst, ta, dir, columns_a, columns, map_a);
+            copy_tiles(&mut dst, tb, dir, columns_b, columns, map_b);
+            dst.save(&dir.join(output));
+            Some(output.to_string_lossy().into_owned())
+        }
+        (None, None) => None,
+        _ => panic!("Only one of the tilesets has an image"),
+    };
+
+    let firstgids = map.tilesets.iter().map(|t| t.firstgid).collect::<Vec<_>>();
+
+    let mut tb = map.tilesets.remove(b);
+    let kept = if b < a { a - 1 } else { a };
+    remap_tile_data(&mut tb, map_b);
+    let ta = &mut map.tilesets[kept];
+    remap_tile_data(ta, map_a);
+    ta.columns = columns;
+    ta.tilecount = (rows_a + rows_b) * columns;
+    if let (Some(image), Some(source)) = (&mut ta.image, image) {
+        image.source = source;
+        image.width = width;
+        image.height = height;
+    }
+    ta.tiles.append(&mut tb.tiles);
+    ta.tiles.sort_by_key(|tile| tile.id);
+    if let Some(mut wangsets_b) = tb.wangsets {
+        match &mut ta.wangsets {
+            Some(wangsets_a) => wangsets_a.wangsets.append(&mut wangsets_b.wangsets),
+            None => ta.wangsets = Some(wangsets_b),
+        }
+    }
+
+    renumber_firstgids(&mut map.tilesets);
+    let new_firstgids = map.tilesets.iter().map(|t| t.firstgid).collect::<Vec<_>>();
+    remap_gids(map, |gid| {
+        let Some(i) = index_for_gid(&firstgids, gid) else {
+            return gid;
+        };
+        let id = gid - firstgids[i];
+        if i == a {
+            new_firstgids[kept] + map_a(id)
+        } else if i == b {
+            new_firstgids[kept] + map_b(id)
+        } else {
+            new_firstgids[if i > b { i - 1 } else { i }] + id
+        }
+    });
+}
+
+pub fn read_permutation(path: &Path) -> HashMap<u32, u32> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .expect("Should have been able to read the permutation file");
+    let mut res = HashMap::new();
+    for record in reader.deserialize() {
+        let (old, new): (u32, u32) = record.expect("Expected `old,new` tile ids");
+        assert!(
+            res.insert(old, new).is_none(),
+            "Tile {old} is moved more than once"
+        );
+    }
+    res
+}
+
+/// Applies a permutation of local tile ids to a tileset; tiles that aren't
+/// listed keep their place
+pub fn reorder<T>(map: &mut Map<T>, name: &str, permutation: &HashMap<u32, u32>)
+where
+    T: SerializationFormat,
+{
+    let index = embedded_tileset_index(map, name);
+    let tileset = &mut map.tilesets[index];
+    let targets = permutation.values().collect::<HashSet<_>>();
+    assert!(
+        targets.len() == permutation.len(),
+        "Several tiles are moved to the same place"
+    );
+    for (old, new) in permutation {
+        assert!(
+            *old < tileset.tilecount && *new < tileset.tilecount,
+            "Tile {old} or {new} is outside of the tileset"
+        );
+        assert!(
+            permutation.contains_key(new),
+            "Tile {new} is replaced but not moved anywhere"
+        );
+    }
+
+    let f = |id: u32| permutation.get(&id).copied().unwrap_or(id);
+    remap_tile_data(tileset, f);
+    tileset.tiles.sort_by_key(|tile| tile.id);
+
+    let firstgid = tileset.firstgid;
+    let tilesets = map.tilesets.iter().map(|t| t.firstgid).collect::<Vec<_>>();
+    remap_gids(map, |gid| match index_for_gid(&tilesets, gid) {
+        Some(i) if i == index => firstgid + f(gid - firstgid),
+        _ => gid,
+    });
+}
+
+fn remove_tile_objects<T>(layers: &mut [LayerType<T>], range: &std::ops::Range<u32>)
+where
+    T: SerializationFormat,
+{
+    for layer in layers.iter_mut().filter_map(|x| x.layer_mut()) {
+        layer.objects.retain(|object| {
+            !object
+                .gid
+                .is_some_and(|gid| range.contains(&(gid & GID_MASK)))
+        });
+        remove_tile_objects(&mut layer.layers, range);
+    }
+}
+
+pub fn remove<T>(map: &mut Map<T>, name: &str, fallback: Option<u32>)
+where
+    T: SerializationFormat,
+{
+    let index = tileset_index(map, name);
+    let removed = map.tilesets.remove(index);
+    let next = map
+        .tilesets
+        .iter()
+        .map(|t| t.firstgid)
+        .filter(|&firstgid| firstgid > removed.firstgid)
+        .min();
+    let end = next.unwrap_or(removed.firstgid + removed.tilecount);
+    let range = removed.firstgid..end;
+    let shift = next.map_or(0, |next| next - removed.firstgid);
+    for tileset in &mut map.tilesets {
+        if tileset.firstgid > removed.firstgid {
+            tileset.firstgid -= shift;
+        }
+    }
+
+    if let Some(fallback) = fallback {
+        assert!(
+            !range.contains(&fallback),
+            "Fallback tile is in the removed tileset"
+        );
+    } else {
+        remove_tile_objects(&mut map.layers, &range);
+    }
+    let shifted = |gid: u32| if gid >= end { gid - shift } else { gid };
+    let fallback = fallback.map_or(0, shifted);
+    remap_gids(map, |gid| {
+        if range.contains(&gid) {
+            fallback
+        } else {
+            shifted(gid)
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{XmlFormat, FLIPPED_HORIZONTALLY_FLAG};
+    use quick_xml::de::from_str;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Writes a 1x1 pixel per tile image with `pixel(id)` as the color of each tile
+    fn image(dir: &Path, name: &str, columns: u32, count: u32, pixel: impl Fn(u32) -> [u8; 4]) {
+        let mut atlas = Atlas::new(columns, count.div_ceil(columns));
+        for id in 0..count {
+            atlas.put_rect(id % columns, id / columns, 1, &pixel(id));
+        }
+        atlas.save(&dir.join(name));
+    }
+
+    fn tileset(firstgid: u32, name: &str, columns: u32, count: u32) -> String {
+        format!(
+            r#"<tileset firstgid="{firstgid}" name="{name}" tilewidth="1" tileheight="1"
+                tilecount="{count}" columns="{columns}">
+                <image source="{name}.png" width="{columns}" height="{}"/>
+            </tileset>"#,
+            count.div_ceil(columns)
+        )
+    }
+
+    /// Map with tilesets a (2 columns, 4 tiles), b (3 columns, 3 tiles) and
+    /// c (2 tiles), and images for a and b in a fresh directory
+    fn setup(test: &str, cells: &str) -> (PathBuf, Map<XmlFormat>) {
+        let dir = std::env::temp_dir().join(format!("tmx-util-tileset-{test}"));
+        fs::create_dir_all(&dir).unwrap();
+        image(&dir, "a.png", 2, 4, |id| [10 + id as u8, 0, 0, 255]);
+        image(&dir, "b.png", 3, 3, |id| [0, 10 + id as u8, 0, 255]);
+        let map = from_str(&format!(
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="5" height="1" tilewidth="1" tileheight="1" nextobjectid="1">
+                {}{}
+                <tileset firstgid="8" name="c" tilewidth="1" tileheight="1"
+                    tilecount="2" columns="2"/>
+                <layer id="1" name="Ground" width="5" height="1">
+                    <data encoding="csv">{cells}</data>
+                </layer>
+            </map>"#,
+            tileset(1, "a", 2, 4),
+            tileset(5, "b", 3, 3)
+        ))
+        .unwrap();
+        (dir, map)
+    }
+
+    fn cells(map: &Map<XmlFormat>) -> Vec<u32> {
+        let layer = map.layers[0].layer().unwrap();
+        layer.data.as_ref().unwrap().data.0.concat()
+    }
+
+    #[test]
+    fn stacks_images_and_remaps_gids() {
+        let h = FLIPPED_HORIZONTALLY_FLAG;
+        let (dir, mut map) = setup("merge", &format!("1,4,{},7,8", 5 | h));
+        merge(&mut map, &dir, "a", "b", Some(Path::new("ab.png")));
+
+        let names = map
+            .tilesets
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "c"]);
+        let merged = &map.tilesets[0];
+        assert_eq!((merged.columns, merged.tilecount), (3, 9));
+        assert_eq!(map.tilesets[1].firstgid, 10);
+        // a: 0 1 _ / 2 3 _ and b below it, flags are kept
+        assert_eq!(cells(&map), [1, 5, 7 | h, 9, 10]);
+
+        let image = merged.image.as_ref().unwrap();
+        assert_eq!(
+            (image.source.as_str(), image.width, image.height),
+            ("ab.png", 3, 3)
+        );
+        let atlas = Atlas::open(&dir.join("ab.png"));
+        let pixel = |x, y| atlas.rect(x, y, 1, 1);
+        assert_eq!(pixel(0, 0), [10, 0, 0, 255]);
+        assert_eq!(pixel(1, 1), [13, 0, 0, 255]);
+        assert_eq!(pixel(2, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(2, 2), [0, 12, 0, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "pass --image to save the merged one")]
+    fn needs_an_output_for_the_merged_image() {
+        let (dir, mut map) = setup("merge-without-image", "1,0,0,0,0");
+        merge(&mut map, &dir, "a", "b", None);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{for_each_layer, Map, SerializationFormat, TileSet};
+use quick_xml::de::from_str;
+use std::fs;
+use std::path::Path;
+
+/// Replaces the tileset with the same name as the one in `path`, keeping its firstgid
+fn swap_tileset<T>(map: &mut Map<T>, dir: &Path, path: &Path)
+where
+    T: SerializationFormat,
+{
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Can't read {}: {err}", path.display()));
+    let mut loaded: TileSet<T> = match from_str(&contents) {
+        Ok(tileset) => tileset,
+        Err(err) => panic!("{}: {:?}", path.display(), err),
+    };
+    let tileset = map
+        .tilesets
+        .iter_mut()
+        .find(|tileset| tileset.name == loaded.name)
+        .unwrap_or_else(|| panic!("No tileset named {}", loaded.name));
+    if loaded.tilecount < tileset.tilecount {
+        eprintln!(
+            "Tileset {} has {} tiles instead of {}",
+            loaded.name, loaded.tilecount, tileset.tilecount
+        );
+    }
+    let source = fs::canonicalize(path)
+        .ok()
+        .zip(fs::canonicalize(dir).ok())
+        .and_then(|(path, dir)| Some(path.strip_prefix(dir).ok()?.to_path_buf()))
+        .unwrap_or_else(|| path.to_path_buf());
+    loaded.firstgid = tileset.firstgid;
+    loaded.source = Some(source.to_string_lossy().into_owned());
+    *tileset = loaded;
+}
+
+/// Changes the tile size of the map and scales everything measured in pixels
+/// by the same factor, so objects stay on the same tiles
+pub fn set_tile_size<T>(
+    map: &mut Map<T>,
+    dir: &Path,
+    tilewidth: u32,
+    tileheight: u32,
+    tilesets: &[impl AsRef<Path>],
+) where
+    T: SerializationFormat,
+{
+    assert!(tilewidth > 0 && tileheight > 0, "Tile size can't be zero");
+    for path in tilesets {
+        swap_tileset(map, dir, path.as_ref());
+    }
+    for tileset in &map.tilesets {
+        if tileset.tilewidth != tilewidth || tileset.tileheight != tileheight {
+            eprintln!(
+                "Tileset {} still has {}x{} tiles",
+                tileset.name, tileset.tilewidth, tileset.tileheight
+            );
+        }
+    }
+
+    let sx = tilewidth as f64 / map.tilewidth as f64;
+    let sy = tileheight as f64 / map.tileheight as f64;
+    map.tilewidth = tilewidth;
+    map.tileheight = tileheight;
+    for_each_layer(&mut map.layers, &mut |layer| {
+        if let Some(offsetx) = &mut layer.offsetx {
+            *offsetx *= sx;
+        }
+        if let Some(offsety) = &mut layer.offsety {
+            *offsety *= sy;
+        }
+        for object in &mut layer.objects {
+            object.x *= sx;
+            object.y *= sy;
+            if let Some(width) = &mut object.width {
+                *width *= sx;
+            }
+            if let Some(height) = &mut object.height {
+                *height *= sy;
+            }
+            for points in [&mut object.polygon, &mut object.polyline]
+                .into_iter()
+                .flatten()
+            {
+                for (x, y) in &mut points.points {
+                    *x *= sx;
+                    *y *= sy;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,292 @@
+use crate::{
+    for_each_layer, Map, Object, SerializationFormat, Transformation, FLIPPED_DIAGONALLY_FLAG,
+    FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG,
+};
+
+/// Linear map of screen coordinates (y pointing down)
+type Matrix = [[i32; 2]; 2];
+
+const IDENTITY: Matrix = [[1, 0], [0, 1]];
+const DIAGONAL: Matrix = [[0, 1], [1, 0]];
+const HORIZONTAL: Matrix = [[-1, 0], [0, 1]];
+const VERTICAL: Matrix = [[1, 0], [0, -1]];
+
+fn mul(a: Matrix, b: Matrix) -> Matrix {
+    std::array::from_fn(|i| std::array::from_fn(|j| a[i][0] * b[0][j] + a[i][1] * b[1][j]))
+}
+
+fn apply(m: Matrix, (x, y): (f64, f64)) -> (f64, f64) {
+    (
+        m[0][0] as f64 * x + m[0][1] as f64 * y,
+        m[1][0] as f64 * x + m[1][1] as f64 * y,
+    )
+}
+
+impl Transformation {
+    fn matrix(self) -> Matrix {
+        use Transformation::*;
+        match self {
+            FlipHorizontal => HORIZONTAL,
+            FlipVertical => VERTICAL,
+            Rotate90 => [[0, -1], [1, 0]],
+            Rotate180 => [[-1, 0], [0, -1]],
+            Rotate270 => [[0, 1], [-1, 0]],
+        }
+    }
+
+    fn swaps_axes(self) -> bool {
+        matches!(self, Transformation::Rotate90 | Transformation::Rotate270)
+    }
+}
+
+/// Tiled flips a tile diagonally first, then horizontally, then vertically
+fn flags_matrix(gid: u32) -> Matrix {
+    let mut res = IDENTITY;
+    if gid & FLIPPED_DIAGONALLY_FLAG != 0 {
+        res = mul(DIAGONAL, res);
+    }
+    if gid & FLIPPED_HORIZONTALLY_FLAG != 0 {
+        res = mul(HORIZONTAL, res);
+    }
+    if gid & FLIPPED_VERTICALLY_FLAG != 0 {
+        res = mul(VERTICAL, res);
+    }
+    res
+}
+
+/// Applies `m` on top of the flip flags of a cell
+fn transform_gid(gid: u32, m: Matrix) -> u32 {
+    if gid == 0 {
+        return 0;
+    }
+    const FLAGS: u32 =
+        FLIPPED_DIAGONALLY_FLAG | FLIPPED_HORIZONTALLY_FLAG | FLIPPED_VERTICALLY_FLAG;
+    let wanted = mul(m, flags_matrix(gid));
+    let flags = (0..8)
+        .map(|i| {
+            [
+                FLIPPED_DIAGONALLY_FLAG,
+                FLIPPED_HORIZONTALLY_FLAG,
+                FLIPPED_VERTICALLY_FLAG,
+            ]
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| i & (1 << bit) != 0)
+            .fold(0, |flags, (_, flag)| flags | flag)
+        })
+        .find(|&flags| flags_matrix(flags) == wanted)
+        .expect("Flags cover all rotations and flips");
+    gid & !FLAGS | flags
+}
+
+fn transform_grid(grid: &[Vec<u32>], transformation: Transformation) -> Vec<Vec<u32>> {
+    let m = transformation.matrix();
+    let height = grid.len() as i32;
+    let width = grid.first().map_or(0, |row| row.len()) as i32;
+    let (new_width, new_height) = if transformation.swaps_axes() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let mut res = vec![vec![0; new_width as usize]; new_height as usize];
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &gid) in row.iter().enumerate() {
+            // Doubled coordinates of the cell center relative to the grid center
+            let cx = 2 * x as i32 + 1 - width;
+            let cy = 2 * y as i32 + 1 - height;
+            let nx = m[0][0] * cx + m[0][1] * cy;
+            let ny = m[1][0] * cx + m[1][1] * cy;
+            let (nx, ny) = ((nx + new_width - 1) / 2, (ny + new_height - 1) / 2);
+            res[ny as usize][nx as usize] = transform_gid(gid, m);
+        }
+    }
+    res
+}
+
+/// Drops float noise like `15.999999999999998` left by `sin` and `cos` of right angles,
+/// and turns `-0` into `0` so that it isn't written out
+fn round(value: f64) -> f64 {
+    (value * 1e9).round() / 1e9 + 0.0
+}
+
+fn transform_object<T>(
+    object: &mut Object<T>,
+    transformation: Transformation,
+    position: impl Fn((f64, f64)) -> (f64, f64),
+) where
+    T: SerializationFormat,
+{
+    use Transformation::*;
+    let rotation = object.rotation.unwrap_or(0.0);
+    let (x, y) = position((object.x, object.y));
+    let (width, height) = (object.width.unwrap_or(0.0), object.height.unwrap_or(0.0));
+    let points = object
+        .polygon
+        .iter_mut()
+        .chain(object.polyline.iter_mut())
+        .flat_map(|points| points.points.iter_mut());
+    let new_rotation = match transformation {
+        FlipHorizontal | FlipVertical => {
+            // Mirroring turns the object frame the other way around, so the
+            // origin moves to the opposite corner of the shape
+            let r = (-rotation).to_radians();
+            let (u, v) = ((r.cos(), r.sin()), (-r.sin(), r.cos()));
+            let (dx, dy) = if transformation == FlipHorizontal {
+                points.for_each(|(px, _)| *px = round(-*px));
+                (-width * u.0, -width * u.1)
+            } else {
+                points.for_each(|(_, py)| *py = round(-*py));
+                // Tile objects are placed by their bottom left corner
+                let h = if object.gid.is_some() {
+                    height
+                } else {
+                    -height
+                };
+                (h * v.0, h * v.1)
+            };
+            if let Some(gid) = &mut object.gid {
+                *gid ^= if transformation == FlipHorizontal {
+                    FLIPPED_HORIZONTALLY_FLAG
+                } else {
+                    FLIPPED_VERTICALLY_FLAG
+                };
+            }
+            object.x = round(x + dx);
+            object.y = round(y + dy);
+            -rotation
+        }
+        Rotate90 | Rotate180 | Rotate270 => {
+            object.x = x;
+            object.y = y;
+            rotation
+                + match transformation {
+                    Rotate90 => 90.0,
+                    Rotate180 => 180.0,
+                    _ => 270.0,
+                }
+        }
+    };
+    let new_rotation = new_rotation.rem_euclid(360.0);
+    if object.rotation.is_some() || new_rotation != 0.0 {
+        object.rotation = Some(new_rotation);
+    }
+}
+
+/// Mirrors or rotates the whole map around its center
+pub fn transform<T>(map: &mut Map<T>, transformation: Transformation)
+where
+    T: SerializationFormat,
+{
+    assert!(
+        map.orientation == "orthogonal",
+        "Only orthogonal maps can be transformed"
+    );
+    if transformation.swaps_axes() {
+        assert!(
+            map.tilewidth == map.tileheight,
+            "Rotating by 90 degrees needs square tiles"
+        );
+    }
+    let m = transformation.matrix();
+    let (width, height) = (
+        (map.width * map.tilewidth) as f64,
+        (map.height * map.tileheight) as f64,
+    );
+    let (new_width, new_height) = if transformation.swaps_axes() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let position = |(x, y): (f64, f64)| {
+        let (x, y) = apply(m, (x - width / 2.0, y - height / 2.0));
+        (x + new_width / 2.0, y + new_height / 2.0)
+    };
+
+    if transformation.swaps_axes() {
+        std::mem::swap(&mut map.width, &mut map.height);
+    }
+    for_each_layer(&mut map.layers, &mut |layer| {
+        if let Some(data) = &mut layer.data {
+            data.data.0 = transform_grid(&data.data.0, transformation);
+            if transformation.swaps_axes() {
+                std::mem::swap(&mut layer.width, &mut layer.height);
+            }
+        }
+        if layer.offsetx.is_some() || layer.offsety.is_some() {
+            let (x, y) = apply(
+                m,
+                (layer.offsetx.unwrap_or(0.0), layer.offsety.unwrap_or(0.0)),
+            );
+            layer.offsetx = Some(round(x));
+            layer.offsety = Some(round(y));
+        }
+        for object in &mut layer.objects {
+            transform_object(object, transformation, position);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Points, XmlFormat};
+    use quick_xml::de::from_str;
+
+    fn object(xml: &str) -> Object<XmlFormat> {
+        from_str(xml).unwrap()
+    }
+
+    const H: u32 = FLIPPED_HORIZONTALLY_FLAG;
+    const V: u32 = FLIPPED_VERTICALLY_FLAG;
+    const D: u32 = FLIPPED_DIAGONALLY_FLAG;
+
+    #[test]
+    fn composes_flip_flags() {
+        let rotate90 = Transformation::Rotate90.matrix();
+        assert_eq!(transform_gid(0, rotate90), 0);
+        assert_eq!(transform_gid(5, HORIZONTAL), 5 | H);
+        assert_eq!(transform_gid(5 | H, HORIZONTAL), 5);
+        assert_eq!(transform_gid(5, rotate90), 5 | D | H);
+        assert_eq!(
+            transform_gid(5 | H, Transformation::Rotate180.matrix()),
+            5 | V
+        );
+        let turned = (0..4).fold(5 | V, |gid, _| transform_gid(gid, rotate90));
+        assert_eq!(turned, 5 | V);
+    }
+
+    #[test]
+    fn moves_cells_with_the_grid() {
+        let grid = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        assert_eq!(
+            transform_grid(&grid, Transformation::FlipHorizontal),
+            [[3 | H, 2 | H, 1 | H], [6 | H, 5 | H, 4 | H]]
+        );
+        assert_eq!(
+            transform_grid(&grid, Transformation::Rotate90),
+            [[4, 1], [5, 2], [6, 3]].map(|row| row.map(|gid| gid | D | H))
+        );
+        assert_eq!(
+            transform_grid(&grid, Transformation::Rotate270),
+            [[3, 6], [2, 5], [1, 4]].map(|row| row.map(|gid| gid | D | V))
+        );
+    }
+
+    #[test]
+    fn flipping_points_keeps_zero_positive() {
+        let mut polygon =
+            object(r#"<object id="1" x="0" y="0"><polygon points="0,0 8,0 0,8"/></object>"#);
+        transform_object(&mut polygon, Transformation::FlipHorizontal, |p| p);
+        let Some(Points { points, .. }) = &polygon.polygon else {
+            panic!("Polygon is kept");
+        };
+        assert_eq!(points, &[(0.0, 0.0), (-8.0, 0.0), (0.0, 8.0)]);
+        let zeros = points
+            .iter()
+            .flat_map(|&(x, y)| [x, y])
+            .filter(|v| *v == 0.0)
+            .collect::<Vec<_>>();
+        assert_eq!(zeros.len(), 4);
+        assert!(zeros.iter().all(|v| v.is_sign_positive()));
+    }
+}
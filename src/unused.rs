@@ -0,0 +1,126 @@
+use crate::{
+    all_layers, tileset_for_gid, Map, OutputFormat, SerializationFormat, TileSet, GID_MASK,
+};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct Unused {
+    tileset: String,
+    tilecount: u32,
+    maps: Vec<PathBuf>,
+    unused: Vec<u32>,
+}
+
+/// Whether `tileset` is the one called `name` or stored in the .tsx file `name`
+pub fn matches<T>(tileset: &TileSet<T>, map_path: &Path, name: &str) -> bool
+where
+    T: SerializationFormat,
+{
+    if tileset.name == name {
+        return true;
+    }
+    let Some(source) = &tileset.source else {
+        return false;
+    };
+    let dir = map_path.parent().unwrap_or(Path::new(""));
+    match (fs::canonicalize(dir.join(source)), fs::canonicalize(name)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => source == name,
+    }
+}
+
+/// Collapses sorted ids into ranges like `3-5, 7`
+fn ranges(ids: &[u32]) -> String {
+    let mut res: Vec<(u32, u32)> = Vec::new();
+    for &id in ids {
+        match res.last_mut() {
+            Some((_, end)) if *end + 1 == id => *end = id,
+            _ => res.push((id, id)),
+        }
+    }
+    res.iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn unused<T>(maps: &[(PathBuf, Map<T>)], name: &str, format: OutputFormat)
+where
+    T: SerializationFormat,
+{
+    let mut used = BTreeSet::new();
+    let mut res = Unused {
+        tileset: name.to_string(),
+        tilecount: 0,
+        maps: Vec::new(),
+        unused: Vec::new(),
+    };
+    for (path, map) in maps {
+        let Some(tileset) = map
+            .tilesets
+            .iter()
+            .find(|tileset| matches(tileset, path, name))
+        else {
+            eprintln!("{} doesn't use tileset {name}", path.display());
+            continue;
+        };
+        res.tilecount = res.tilecount.max(tileset.tilecount);
+        res.maps.push(path.clone());
+
+        let mut add = |gid: u32| {
+            if gid != 0
+                && tileset_for_gid(&map.tilesets, gid).map(|t| t.firstgid) == Some(tileset.firstgid)
+            {
+                used.insert(gid - tileset.firstgid);
+            }
+        };
+        for (_, layer_type) in all_layers(&map.layers) {
+            let Some(layer) = layer_type.layer() else {
+                continue;
+            };
+            if let Some(data) = &layer.data {
+                data.data
+                    .0
+                    .iter()
+                    .flatten()
+                    .for_each(|&gid| add(gid & GID_MASK));
+            }
+            for object in &layer.objects {
+                if let Some(gid) = object.gid {
+                    add(gid & GID_MASK);
+                }
+            }
+        }
+        for tile in &tileset.tiles {
+            if let Some(animation) = &tile.animation {
+                used.extend(animation.frames.iter().map(|frame| frame.tileid));
+            }
+        }
+    }
+    res.unused = (0..res.tilecount).filter(|id| !used.contains(id)).collect();
+
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{}: {} of {} tiles unused in {} maps",
+                res.tileset,
+                res.unused.len(),
+                res.tilecount,
+                res.maps.len()
+            );
+            if !res.unused.is_empty() {
+                println!("{}", ranges(&res.unused));
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&res).unwrap()),
+    }
+}
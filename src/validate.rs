@@ -0,0 +1,341 @@
+use crate::{
+    all_layers, tileset_for_gid, LayerType, Map, OutputFormat, SerializationFormat, TileSet,
+    GID_MASK,
+};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct Diagnostic {
+    location: String,
+    message: String,
+}
+
+/// Collects problems with the map, each one at a location like `layer Ground`
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn push(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Location of the tileset, by file for tilesets whose .tsx couldn't be read
+fn tileset_location<T>(tileset: &TileSet<T>) -> String
+where
+    T: SerializationFormat,
+{
+    match &tileset.source {
+        Some(source) if tileset.name.is_empty() => format!("tileset {source}"),
+        _ => format!("tileset {}", tileset.name),
+    }
+}
+
+fn check_files<T>(map: &Map<T>, dir: &Path, res: &mut Diagnostics)
+where
+    T: SerializationFormat,
+{
+    for tileset in &map.tilesets {
+        let location = tileset_location(tileset);
+        // Images of external tilesets are relative to the .tsx file
+        let mut image_dir = dir.to_path_buf();
+        if let Some(source) = &tileset.source {
+            let path = dir.join(source);
+            if !path.is_file() {
+                res.push(&location, format!("{source} doesn't exist"));
+                continue;
+            }
+            image_dir = path.parent().unwrap_or(dir).to_path_buf();
+        }
+        if let Some(image) = &tileset.image {
+            if !image_dir.join(&image.source).is_file() {
+                res.push(&location, format!("{} doesn't exist", image.source));
+            }
+        }
+    }
+    for (path, layer_type) in all_layers(&map.layers) {
+        for object in layer_type.layer().into_iter().flat_map(|l| &l.objects) {
+            if let Some(template) = &object.template {
+                if !dir.join(template).is_file() {
+                    let location = format!("layer {path} object {}", object.id);
+                    res.push(location, format!("{template} doesn't exist"));
+                }
+            }
+        }
+    }
+}
+
+fn check_tilesets<T>(map: &Map<T>, res: &mut Diagnostics)
+where
+    T: SerializationFormat,
+{
+    let mut tilesets = map.tilesets.iter().collect::<Vec<_>>();
+    tilesets.sort_by_key(|tileset| tileset.firstgid);
+    for pair in tilesets.windows(2) {
+        if pair[0].firstgid + pair[0].tilecount > pair[1].firstgid {
+            res.push(
+                tileset_location(pair[1]),
+                format!(
+                    "firstgid {} overlaps the tiles of {}",
+                    pair[1].firstgid,
+                    tileset_location(pair[0])
+                ),
+            );
+        }
+    }
+}
+
+fn check_gid<T>(map: &Map<T>, gid: u32, location: impl FnOnce() -> String, res: &mut Diagnostics)
+where
+    T: SerializationFormat,
+{
+    let gid = gid & GID_MASK;
+    if gid == 0 {
+        return;
+    }
+    match tileset_for_gid(&map.tilesets, gid) {
+        // Tiles of missing .tsx files are reported as the missing file
+        Some(tileset) if tileset.source.is_some() && tileset.tilecount == 0 => {}
+        Some(tileset) if gid < tileset.firstgid + tileset.tilecount => {}
+        _ => res.push(location(), format!("gid {gid} isn't in any tileset")),
+    }
+}
+
+fn check_layers<T>(map: &Map<T>, res: &mut Diagnostics)
+where
+    T: SerializationFormat,
+{
+    let mut layer_ids = BTreeSet::new();
+    let mut object_ids = BTreeSet::new();
+    for (path, layer_type) in all_layers(&map.layers) {
+        let Some(layer) = layer_type.layer() else {
+            continue;
+        };
+        let location = format!("layer {path}");
+        if let Some(id) = layer.id {
+            if !layer_ids.insert(id) {
+                res.push(&location, format!("id {id} is used by another layer"));
+            }
+            if map.nextlayerid.is_some_and(|next| id >= next) {
+                res.push(&location, format!("id {id} isn't below nextlayerid"));
+            }
+        }
+
+        if let (LayerType::Layer(_), Some(data)) = (layer_type, &layer.data) {
+            let width = layer.width.unwrap_or(map.width);
+            let height = layer.height.unwrap_or(map.height);
+            if (width, height) != (map.width, map.height) {
+                res.push(
+                    &location,
+                    format!(
+                        "is {width}x{height} tiles but the map is {}x{}",
+                        map.width, map.height
+                    ),
+                );
+            }
+            let rows = &data.data.0;
+            if rows.len() != height as usize {
+                res.push(
+                    &location,
+                    format!("has {} rows of data instead of {height}", rows.len()),
+                );
+            }
+            for (y, row) in rows.iter().enumerate() {
+                if row.len() != width as usize {
+                    res.push(
+                        &location,
+                        format!("row {y} has {} cells instead of {width}", row.len()),
+                    );
+                }
+                for (x, &gid) in row.iter().enumerate() {
+                    check_gid(map, gid, || format!("{location} cell {x},{y}"), res);
+                }
+            }
+        }
+
+        for object in &layer.objects {
+            let location = format!("{location} object {}", object.id);
+            if !object_ids.insert(object.id) {
+                res.push(&location, "id is used by another object");
+            }
+            if object.id >= map.nextobjectid {
+                res.push(&location, "id isn't below nextobjectid");
+            }
+            if let Some(gid) = object.gid {
+                check_gid(map, gid, || location.clone(), res);
+            }
+        }
+    }
+}
+
+fn diagnostics<T>(map: Result<&Map<T>, &String>, path: &Path) -> Diagnostics
+where
+    T: SerializationFormat,
+{
+    let mut res = Diagnostics(Vec::new());
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match map {
+        Ok(map) => {
+            check_files(map, dir, &mut res);
+            check_tilesets(map, &mut res);
+            check_layers(map, &mut res);
+        }
+        Err(err) => res.push("file", format!("can't be read: {err}")),
+    }
+    res
+}
+
+/// Checks the structure of the map read from `path`, or reports why it
+/// couldn't be read, returning whether there were problems
+pub fn validate<T>(map: Result<&Map<T>, &String>, path: &Path, format: OutputFormat) -> bool
+where
+    T: SerializationFormat,
+{
+    let res = diagnostics(map, path);
+
+    match format {
+        OutputFormat::Table => {
+            for diagnostic in &res.0 {
+                println!(
+                    "{}: {}: {}",
+                    path.display(),
+                    diagnostic.location,
+                    diagnostic.message
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&res.0).unwrap()),
+    }
+    !res.0.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::try_read_map;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    /// Writes a 2x1 map with the given content next to a tileset of 2 tiles
+    /// and returns its diagnostics as `location: message`
+    fn check(test: &str, content: &str) -> Vec<String> {
+        let dir = std::env::temp_dir().join(format!("tmx-util-validate-{test}"));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("map.tmx");
+        fs::write(
+            &path,
+            format!(
+                r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                    width="2" height="1" tilewidth="16" tileheight="16"
+                    nextlayerid="3" nextobjectid="2">{content}</map>"#
+            ),
+        )
+        .unwrap();
+        let map = try_read_map(&path);
+        let res = diagnostics(map.as_ref(), &path);
+        res.0
+            .iter()
+            .map(|d| format!("{}: {}", d.location, d.message))
+            .collect()
+    }
+
+    const TILESET: &str = r#"<tileset firstgid="1" name="t" tilewidth="16" tileheight="16"
+        tilecount="2" columns="2"/>"#;
+
+    fn layer(id: u32, data: &str) -> String {
+        format!(r#"<layer id="{id}" name="L{id}" width="2" height="1">{data}</layer>"#)
+    }
+
+    #[test]
+    fn accepts_a_valid_map() {
+        let data = r#"<data encoding="csv">1,2</data>"#;
+        assert!(check("valid", &format!("{TILESET}{}", layer(1, data))).is_empty());
+    }
+
+    #[test]
+    fn decodes_compressed_data() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for gid in [2u32, 3] {
+            encoder.write_all(&gid.to_le_bytes()).unwrap();
+        }
+        let text = STANDARD.encode(encoder.finish().unwrap());
+        let data = format!(r#"<data encoding="base64" compression="zlib">{text}</data>"#);
+        assert_eq!(
+            check("zlib", &format!("{TILESET}{}", layer(1, &data))),
+            ["layer L1 cell 1,0: gid 3 isn't in any tileset"]
+        );
+    }
+
+    #[test]
+    fn reports_data_that_cant_be_parsed() {
+        let data = r#"<data encoding="csv">1,x</data>"#;
+        assert_eq!(
+            check("csv", &format!("{TILESET}{}", layer(1, data))),
+            [r#"file: can't be read: Invalid gid "x""#]
+        );
+    }
+
+    #[test]
+    fn reads_properties_before_tilesets() {
+        let properties = r#"<properties><property name="music" value="a.ogg"/></properties>"#;
+        let data = r#"<data encoding="csv">1,2</data>"#;
+        let content = format!("{properties}{TILESET}{}", layer(1, data));
+        assert!(check("properties", &content).is_empty());
+    }
+
+    #[test]
+    fn reports_structural_problems() {
+        let overlapping = r#"<tileset firstgid="2" name="u" tilewidth="16" tileheight="16"
+            tilecount="2" columns="2"/>"#;
+        let objects = r#"<objectgroup id="1" name="Things">
+            <object id="1"/><object id="1"/><object id="2"/>
+        </objectgroup>"#;
+        let content = format!(
+            "{TILESET}{overlapping}{}{objects}",
+            layer(1, r#"<data encoding="csv">1,2,0</data>"#)
+        );
+        assert_eq!(
+            check("structure", &content),
+            [
+                "tileset u: firstgid 2 overlaps the tiles of tileset t",
+                "layer L1: row 0 has 3 cells instead of 2",
+                "layer Things: id 1 is used by another layer",
+                "layer Things object 1: id is used by another object",
+                "layer Things object 2: id isn't below nextobjectid",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_external_tilesets() {
+        let dir = std::env::temp_dir().join("tmx-util-validate-tsx");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("broken.tsx"), r#"<tileset name="broken""#).unwrap();
+        let path = dir.join("map.tmx");
+        fs::write(
+            &path,
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down"
+                width="1" height="1" tilewidth="16" tileheight="16" nextobjectid="1">
+                <tileset firstgid="1" source="broken.tsx"/>
+                <layer id="1" name="Ground" width="1" height="1">
+                    <data encoding="csv">1</data>
+                </layer>
+            </map>"#,
+        )
+        .unwrap();
+
+        let map = try_read_map(&path);
+        let res = diagnostics(map.as_ref(), &path);
+        assert_eq!(res.0.len(), 1);
+        assert_eq!(res.0[0].location, "file");
+        assert!(res.0[0].message.starts_with("can't be read: broken.tsx: "));
+    }
+}